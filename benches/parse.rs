@@ -0,0 +1,46 @@
+//! Parsing a 20-byte header plus a 1400-byte payload, the owning way and
+//! the borrowing way. `parse_packet` and `parse_packet_raw` differ only in
+//! the lossy `String` made from the payload, so the gap between them is the
+//! cost of that allocation.
+//!
+//! There is no `TcpView` to measure as the zero-copy side:
+//! `parse_packet_raw`, which borrows the payload and only allocates the
+//! options, stands in for it until one exists.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use harbinger::flags::TcpFlags;
+use harbinger::tcp::Tcp;
+use std::net::Ipv4Addr;
+
+fn segment() -> Vec<u8> {
+    let payload = vec![b'x'; 1400];
+    Tcp::builder()
+        .source_port(49320)
+        .dest_port(80)
+        .seq_num(1)
+        .ack_num(1)
+        .flags(TcpFlags::PSH | TcpFlags::ACK)
+        .build(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, &payload)
+        .build_packet(&payload)
+}
+
+fn parse(c: &mut Criterion) {
+    let bytes = segment();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    group.bench_function("try_from", |b| {
+        b.iter(|| Tcp::try_from(black_box(&bytes[..])).unwrap())
+    });
+    group.bench_function("parse_packet", |b| {
+        b.iter(|| Tcp::parse_packet(black_box(&bytes)).unwrap())
+    });
+    group.bench_function("parse_packet_raw", |b| {
+        b.iter(|| Tcp::parse_packet_raw(black_box(&bytes)).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
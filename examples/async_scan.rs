@@ -0,0 +1,90 @@
+use harbinger::{
+    flags::TcpFlags,
+    handshake::{answers, connection_seq},
+    ip::Ipv4Header,
+    raw::{source_ip_for, AsyncRawTcpSocket, RawTcpSocket},
+    tcp::Tcp,
+};
+use std::{collections::HashMap, io, net::Ipv4Addr, sync::Arc, time::Duration};
+use tokio::{task::JoinSet, time::timeout};
+
+/// How long to keep listening after the last answer.
+const QUIET: Duration = Duration::from_secs(1);
+
+/// SYN-scans a port range with every probe in flight at once, sending from
+/// one task per port over a single async raw socket. Needs root.
+///
+///     sudo cargo run --example async_scan -- 127.0.0.1 1-1024
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> io::Result<()> {
+    let usage = "Usage: async_scan <target-ip> <first-port>-<last-port>";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [target, ports] = &args[..] else {
+        panic!("{}", usage);
+    };
+    let target: Ipv4Addr = target.parse().unwrap_or_else(|_| panic!("{}", usage));
+    let (first, last) = ports.split_once('-').unwrap_or((ports, ports));
+    let (first, last): (u16, u16) = first
+        .parse()
+        .and_then(|first| Ok((first, last.parse()?)))
+        .unwrap_or_else(|_| panic!("{}", usage));
+
+    let src = source_ip_for(target)?;
+    let socket = Arc::new(AsyncRawTcpSocket::new(RawTcpSocket::new()?)?);
+
+    let mut syns = HashMap::new();
+    let mut sends = JoinSet::new();
+    for port in first..=last {
+        let mut syn = Tcp::builder()
+            .ephemeral_source_port()
+            .dest_port(port)
+            .flags(TcpFlags::SYN)
+            .mss(1460)
+            .build(src, target, b"");
+        // The ISN depends on the port just picked.
+        syn.seq_num = connection_seq(src, syn.source_port, target, port);
+        syn.recompute_checksum(src, target, b"");
+
+        let socket = socket.clone();
+        let packet = syn.build_packet(b"");
+        sends.spawn(async move { socket.send_to(&packet, target).await });
+        syns.insert(port, syn);
+    }
+    while let Some(sent) = sends.join_next().await {
+        sent.map_err(io::Error::other)??;
+    }
+    println!("Sent {} SYNs to {}", syns.len(), target);
+
+    let mut open = Vec::new();
+    let mut closed = 0;
+    while let Ok(datagram) = timeout(QUIET, socket.recv_from()).await {
+        let (datagram, _) = datagram?;
+        let Ok(ip) = Ipv4Header::try_from(&datagram[..]) else {
+            continue;
+        };
+        if ip.src != target {
+            continue;
+        }
+        let Ok(reply) = Tcp::try_from(&datagram[ip.ihl as usize * 4..]) else {
+            continue;
+        };
+        let Some(syn) = syns.get(&reply.source_port) else {
+            continue;
+        };
+        if !answers(syn, &reply) {
+            continue;
+        }
+
+        syns.remove(&reply.source_port);
+        if reply.is_syn_ack() {
+            open.push(reply.source_port);
+        } else {
+            closed += 1;
+        }
+    }
+
+    open.sort_unstable();
+    println!("Open: {:?}", open);
+    println!("{} closed, {} filtered", closed, syns.len());
+    Ok(())
+}
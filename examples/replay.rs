@@ -0,0 +1,64 @@
+use harbinger::{
+    eth::parse_eth_ipv4_tcp,
+    ip::Ipv4Header,
+    pcap::{PcapReader, LINKTYPE_ETHERNET, LINKTYPE_RAW},
+    tcp::Tcp,
+};
+use std::io;
+
+/// Replays a capture written by `reciever --write`, or an Ethernet capture
+/// such as one from tcpdump, parsing every TCP segment in it.
+///
+///     cargo run --example replay -- out.pcap
+fn main() -> io::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| panic!("Usage: replay <file.pcap>"));
+
+    let reader = PcapReader::open(&path)?;
+    let ethernet = match reader.linktype() {
+        LINKTYPE_RAW => false,
+        LINKTYPE_ETHERNET => true,
+        linktype => panic!(
+            "Only raw IP and Ethernet captures are supported, got linktype {}",
+            linktype
+        ),
+    };
+
+    for (i, record) in reader.enumerate() {
+        let packet = record?;
+
+        if ethernet {
+            match parse_eth_ipv4_tcp(&packet) {
+                Ok((_, ip, tcp, payload)) => {
+                    println!("#{}: {} > {}\n{}", i, ip.src, ip.dst, tcp);
+                    if !payload.is_empty() {
+                        println!("\n{}", String::from_utf8_lossy(payload));
+                    }
+                }
+                Err(e) => eprintln!("#{}: skipping: {}", i, e),
+            }
+            continue;
+        }
+
+        let ip = match Ipv4Header::try_from(&packet[..]) {
+            Ok(ip) => ip,
+            Err(e) => {
+                eprintln!("#{}: skipping: {}", i, e);
+                continue;
+            }
+        };
+
+        match Tcp::parse_packet(&packet[ip.ihl as usize * 4..]) {
+            Ok((tcp, payload)) => {
+                println!("#{}: {} > {}\n{}", i, ip.src, ip.dst, tcp);
+                if let Some(payload) = payload {
+                    println!("\n{}", payload);
+                }
+            }
+            Err(e) => eprintln!("#{}: skipping: {}", i, e),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use harbinger::{
+    checksum::{PROTOCOL_ICMP, PROTOCOL_TCP},
+    flags::TcpFlags,
+    icmp::{self, ICMP_TIME_EXCEEDED},
+    ip::{build_ipv4_tcp, Ipv4Header},
+    raw::{source_ip_for, RawIcmpSocket, RawTcpSocket},
+    tcp::Tcp,
+};
+use std::{
+    io,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+const MAX_HOPS: u8 = 30;
+
+/// How long to wait for each hop to answer.
+const HOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long each socket is read before switching to the other.
+const POLL: Duration = Duration::from_millis(20);
+
+/// What came back for one probe.
+enum Answer {
+    /// A router on the way sent Time Exceeded.
+    Hop(Ipv4Addr),
+    /// The target itself answered, with a SYN-ACK or a RST.
+    Reached(Tcp),
+}
+
+/// Traces the route to a TCP port by sending SYNs with TTLs 1, 2, ... and
+/// printing the router that reports each one expiring, until the target
+/// answers. TCP probes get through firewalls that drop the usual UDP or
+/// ICMP ones. Needs root.
+///
+///     sudo cargo run --example traceroute -- 192.168.1.1 443
+fn main() -> io::Result<()> {
+    let usage = "Usage: traceroute <target-ip> [port]";
+    let mut args = std::env::args().skip(1);
+    let target: Ipv4Addr = args
+        .next()
+        .and_then(|ip| ip.parse().ok())
+        .unwrap_or_else(|| panic!("{}", usage));
+    let port: u16 = args
+        .next()
+        .map(|port| port.parse().unwrap_or_else(|_| panic!("{}", usage)))
+        .unwrap_or(80);
+
+    let src = source_ip_for(target)?;
+    // The IP header is built here, so each probe can have its own TTL.
+    let tcp_socket = RawTcpSocket::with_ip_header()?;
+    tcp_socket.set_read_timeout(Some(POLL))?;
+    let icmp_socket = RawIcmpSocket::new()?;
+    icmp_socket.set_read_timeout(Some(POLL))?;
+
+    let mut syn = Tcp::builder();
+    syn.ephemeral_source_port()
+        .dest_port(port)
+        .flags(TcpFlags::SYN)
+        .mss(1460);
+
+    println!("traceroute to {}:{}, {} hops max", target, port, MAX_HOPS);
+    for ttl in 1..=MAX_HOPS {
+        let probe = syn.seq_num(ttl as u32).build(src, target, b"");
+        let mut ip = Ipv4Header::new(src, target, PROTOCOL_TCP);
+        ip.ttl = ttl;
+        tcp_socket.send(&build_ipv4_tcp(ip, &probe, b""), target)?;
+        let sent = Instant::now();
+
+        match wait_for_answer(&tcp_socket, &icmp_socket, &probe, target, sent) {
+            Some(Answer::Hop(router)) => {
+                println!("{:2}  {}  {:?}", ttl, router, sent.elapsed())
+            }
+            Some(Answer::Reached(reply)) => {
+                println!("{:2}  {}  {:?}  {:#}", ttl, target, sent.elapsed(), reply);
+                return Ok(());
+            }
+            None => println!("{:2}  *", ttl),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads both sockets in turn until one has an answer to `probe`, or
+/// `HOP_TIMEOUT` after `sent`.
+fn wait_for_answer(
+    tcp_socket: &RawTcpSocket,
+    icmp_socket: &RawIcmpSocket,
+    probe: &Tcp,
+    target: Ipv4Addr,
+    sent: Instant,
+) -> Option<Answer> {
+    while sent.elapsed() < HOP_TIMEOUT {
+        if let Ok((datagram, _)) = icmp_socket.recv() {
+            if let Ok((ip, _, message)) = Ipv4Header::parse_packet_raw(&datagram) {
+                // The quoted SYN tells this probe's expiry from a late one.
+                if let Ok((icmp, original)) = icmp::parse_error_message(message) {
+                    if ip.protocol == PROTOCOL_ICMP
+                        && icmp.type_ == ICMP_TIME_EXCEEDED
+                        && original.ports() == Some((probe.source_port, probe.dest_port))
+                        && original.tcp_seq() == Some(probe.seq_num)
+                    {
+                        return Some(Answer::Hop(ip.src));
+                    }
+                }
+            }
+        }
+
+        if let Ok((datagram, _)) = tcp_socket.recv() {
+            if let Ok((ip, _, segment)) = Ipv4Header::parse_packet_raw(&datagram) {
+                if let Ok(reply) = Tcp::try_from(segment) {
+                    if ip.src == target
+                        && reply.source_port == probe.dest_port
+                        && reply.dest_port == probe.source_port
+                    {
+                        return Some(Answer::Reached(reply));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
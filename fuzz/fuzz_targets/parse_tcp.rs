@@ -0,0 +1,16 @@
+#![no_main]
+
+use harbinger::tcp::Tcp;
+use libfuzzer_sys::fuzz_target;
+
+// Parsing must be total: any byte slice gives a header or an error, never a
+// panic. Whatever parses must also serialize back to the same header.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tcp) = Tcp::try_from(data) {
+        let reparsed = Tcp::try_from(&tcp.serialize()[..]).expect("serialized header parses");
+        assert_eq!(reparsed, tcp);
+    }
+
+    let _ = Tcp::parse_packet(data);
+    let _ = Tcp::parse_packet_raw(data);
+});
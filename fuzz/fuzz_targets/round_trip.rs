@@ -0,0 +1,10 @@
+#![no_main]
+
+use harbinger::tcp::Tcp;
+use libfuzzer_sys::fuzz_target;
+
+// Any header the serializer can be handed must parse back unchanged.
+fuzz_target!(|tcp: Tcp| {
+    let parsed = Tcp::try_from(&tcp.serialize()[..]).expect("serialized header parses");
+    assert_eq!(parsed, tcp);
+});
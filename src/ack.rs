@@ -0,0 +1,168 @@
+//! Cumulative and delayed acknowledgments (RFC 1122 4.2.3.2, RFC 5681 4.2).
+//!
+//! In-order data isn't acknowledged right away: an ACK goes out for every
+//! second full segment, or once the delay timer (at most 500 ms) runs out,
+//! whichever comes first. Anything out of order is acknowledged at once, so
+//! the sender sees duplicate ACKs and learns about the gap.
+
+use crate::seq::{seq_gt, seq_leq};
+use crate::tcp::Tcp;
+use std::time::{Duration, Instant};
+
+/// The longest RFC 1122 allows an ACK to be delayed.
+pub const MAX_ACK_DELAY: Duration = Duration::from_millis(500);
+
+/// Decides when to acknowledge the peer's data, and with what number.
+#[derive(Debug)]
+pub struct AckManager {
+    /// Next sequence number expected, what an ACK would carry.
+    rcv_nxt: u32,
+    delay: Duration,
+    /// In-order segments received since the last ACK.
+    unacked_segments: u32,
+    deadline: Option<Instant>,
+}
+
+impl AckManager {
+    /// Starts expecting `rcv_nxt`, delaying ACKs by up to `MAX_ACK_DELAY`.
+    pub fn new(rcv_nxt: u32) -> Self {
+        Self::with_delay(rcv_nxt, MAX_ACK_DELAY)
+    }
+
+    /// Like `new` with a shorter delay. Longer ones are capped at
+    /// `MAX_ACK_DELAY`.
+    pub fn with_delay(rcv_nxt: u32, delay: Duration) -> Self {
+        Self {
+            rcv_nxt,
+            delay: delay.min(MAX_ACK_DELAY),
+            unacked_segments: 0,
+            deadline: None,
+        }
+    }
+
+    /// The highest in-order sequence number received, plus one.
+    pub fn ack_num(&self) -> u32 {
+        self.rcv_nxt
+    }
+
+    /// The ACK being held back, if any: the number it will carry.
+    pub fn pending_ack(&self) -> Option<u32> {
+        self.deadline.map(|_| self.rcv_nxt)
+    }
+
+    /// How many in-order segments the pending ACK covers.
+    pub fn pending_segments(&self) -> u32 {
+        self.unacked_segments
+    }
+
+    /// When the pending ACK has to go out at the latest.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Records a segment of `len` bytes at `seq`, returning the ACK number
+    /// to send right away, if one is due.
+    pub fn on_segment(&mut self, seq: u32, len: u32, now: Instant) -> Option<u32> {
+        if len == 0 {
+            return None;
+        }
+
+        let end = seq.wrapping_add(len);
+        if seq_gt(seq, self.rcv_nxt) || seq_leq(end, self.rcv_nxt) {
+            // A gap or a duplicate: tell the sender where we are at once.
+            return Some(self.ack_now());
+        }
+
+        self.rcv_nxt = end;
+        self.unacked_segments += 1;
+        if self.unacked_segments >= 2 {
+            return Some(self.ack_now());
+        }
+        self.deadline.get_or_insert(now + self.delay);
+        None
+    }
+
+    /// Like `on_segment` for a parsed segment carrying `payload_len` bytes,
+    /// counting its SYN and FIN, which have to be acknowledged too.
+    pub fn on_tcp(&mut self, tcp: &Tcp, payload_len: usize, now: Instant) -> Option<u32> {
+        self.on_segment(tcp.seq_num, tcp.segment_len(payload_len), now)
+    }
+
+    /// Returns the ACK number to send if the delay timer has run out by
+    /// `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<u32> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => Some(self.ack_now()),
+            _ => None,
+        }
+    }
+
+    fn ack_now(&mut self) -> u32 {
+        self.unacked_segments = 0;
+        self.deadline = None;
+        self.rcv_nxt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::flags::TcpFlags;
+    use crate::tcp::TcpBuilder;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_one_ack_covers_two_segments() {
+        let now = Instant::now();
+        let mut acks = AckManager::new(1000);
+
+        assert_eq!(acks.on_segment(1000, 100, now), None);
+        assert_eq!(acks.pending_ack(), Some(1100));
+        assert_eq!(acks.pending_segments(), 1);
+
+        assert_eq!(acks.on_segment(1100, 100, now), Some(1200));
+        assert_eq!(acks.pending_ack(), None);
+        assert_eq!(acks.pending_segments(), 0);
+    }
+
+    #[test]
+    fn test_delayed_ack_timer() {
+        let now = Instant::now();
+        let mut acks = AckManager::with_delay(0, Duration::from_secs(5));
+        assert_eq!(acks.delay, MAX_ACK_DELAY);
+
+        acks.on_segment(0, 10, now);
+        assert_eq!(acks.deadline(), Some(now + MAX_ACK_DELAY));
+        assert_eq!(acks.poll(now + Duration::from_millis(499)), None);
+        assert_eq!(acks.poll(now + MAX_ACK_DELAY), Some(10));
+        assert_eq!(acks.poll(now + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn test_out_of_order_acks_immediately() {
+        let now = Instant::now();
+        let mut acks = AckManager::new(u32::MAX - 4);
+
+        // In order across the wrap, then a gap, then a duplicate.
+        assert_eq!(acks.on_segment(u32::MAX - 4, 10, now), None);
+        assert_eq!(acks.on_segment(20, 10, now), Some(5));
+        assert_eq!(acks.pending_ack(), None);
+        assert_eq!(acks.on_segment(u32::MAX - 4, 10, now), Some(5));
+        assert_eq!(acks.on_segment(5, 0, now), None);
+    }
+
+    #[test]
+    fn test_fin_is_acknowledged() {
+        let now = Instant::now();
+        let mut acks = AckManager::new(1000);
+        let fin = TcpBuilder::new()
+            .seq_num(1000)
+            .flags(TcpFlags::FIN | TcpFlags::ACK)
+            .build(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, b"");
+
+        // No payload, but the FIN takes a sequence number.
+        assert_eq!(acks.on_tcp(&fin, 0, now), None);
+        assert_eq!(acks.pending_ack(), Some(1001));
+    }
+}
@@ -1,43 +1,336 @@
 use core::panic;
-use harbinger::tcp::Tcp;
-use socket2::{Domain, SockAddr, Socket, Type};
+use harbinger::{
+    checksum::{PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP},
+    error::RawSocketError,
+    flags::TcpFlags,
+    hexdump::hexdump_at,
+    ip::{verify_header_checksum, Ipv4Header},
+    pcap::PcapWriter,
+    raw::RawTcpSocket,
+    tcp::Tcp,
+};
 use std::{
-    io::{self},
-    mem::MaybeUninit,
-    net::Ipv4Addr,
+    collections::{BTreeMap, HashMap},
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, UNIX_EPOCH},
 };
 
+/// How often the receive loop wakes up to check for Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Set by the Ctrl-C handler; the receive loop stops at its next wakeup.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 fn main() -> io::Result<()> {
-    let receiver = Socket::new(Domain::IPV4, Type::RAW, None)
+    let mut pcap = None;
+    let mut hex = false;
+    let mut json = false;
+    let mut count = 0;
+    let mut protocol = None;
+    let mut port = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--write" => {
+                let path = args
+                    .next()
+                    .unwrap_or_else(|| panic!("--write expects a file path"));
+                pcap = Some(
+                    PcapWriter::create(&path)
+                        .unwrap_or_else(|e| panic!("Failed to create {}: {}", path, e)),
+                );
+            }
+            "--hex" => hex = true,
+            "--json" => json = true,
+            "--count" => {
+                count = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| panic!("--count expects a number of packets"));
+            }
+            "--proto" => {
+                protocol = match args.next().as_deref() {
+                    Some("tcp") => Some(PROTOCOL_TCP),
+                    Some("udp") => Some(PROTOCOL_UDP),
+                    Some("icmp") => Some(PROTOCOL_ICMP),
+                    Some(number) => Some(number.parse().unwrap_or_else(|_| {
+                        panic!("--proto expects tcp, udp, icmp or a protocol number")
+                    })),
+                    None => panic!("--proto expects tcp, udp, icmp or a protocol number"),
+                };
+            }
+            "--port" => {
+                port = Some(
+                    args.next()
+                        .and_then(|port| port.parse().ok())
+                        .unwrap_or_else(|| panic!("--port expects a port number")),
+                );
+            }
+            _ => panic!(
+                "Unknown argument: {}\nUsage: reciever [--write out.pcap] [--hex] [--json] [--count N] [--proto P] [--port N]",
+                arg
+            ),
+        }
+    }
+
+    if json && cfg!(not(feature = "serde")) {
+        panic!("--json needs the serde feature: cargo run --features serde --bin reciever");
+    }
+
+    let receiver = RawTcpSocket::new()
         .unwrap_or_else(|e| panic!("Failed to create a recevier socket.\n{}", e));
 
     let local_ip = Ipv4Addr::new(127, 0, 0, 1);
-    let receiver_sock_addr = SockAddr::from(std::net::SocketAddr::new(local_ip.into(), 0));
     receiver
-        .bind(&receiver_sock_addr)
+        .bind(local_ip)
         .unwrap_or_else(|e| panic!("Failed to bind to receiver raw socket: {}", e));
+    if protocol.is_some() || port.is_some() {
+        attach_filter(&receiver, protocol, port);
+    }
+    // Wakes up now and then so Ctrl-C is noticed without a packet arriving.
+    receiver.set_read_timeout(Some(POLL_INTERVAL))?;
+    handle_ctrl_c();
 
-    // Recieving a packet.
-    let mut buffer: [MaybeUninit<u8>; 1024] = unsafe { MaybeUninit::uninit().assume_init() };
-    let (bytes_read, sender_addr) = receiver
-        .recv_from(&mut buffer)
-        .unwrap_or_else(|e| panic!("Failed to recv_from: {}", e));
-
-    let recieved_data =
-        unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, bytes_read) };
+    // Packets seen per flag combination, with `None` for those that weren't
+    // TCP or didn't parse.
+    let mut seen: BTreeMap<Option<u8>, usize> = BTreeMap::new();
+    let mut scales = WindowScales::default();
+    let mut received = 0;
+    while (count == 0 || received < count) && !INTERRUPTED.load(Ordering::Relaxed) {
+        let (data, sender_addr, timestamp) = match receiver.recv_with_timestamp() {
+            Ok(packet) => packet,
+            Err(RawSocketError::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => panic!("Failed to recv_from: {}", e),
+        };
+        received += 1;
 
-    let ip_header_len = ((recieved_data[0] & 0x0F) * 4) as usize;
-    let tcp_data = &recieved_data[ip_header_len..];
+        if let Some(pcap) = pcap.as_mut() {
+            pcap.write_packet(&data, UNIX_EPOCH + timestamp)?;
+            pcap.flush()?;
+        }
 
-    let (tcp, payload) = Tcp::parse_packet(tcp_data);
-    println!(
-        "Recieved {} bytes from {:?}: {}",
-        bytes_read, sender_addr, tcp
-    );
+        let flags = handle_packet(&data, sender_addr, hex, json, &mut scales);
+        *seen.entry(flags.map(|flags| flags.bits())).or_default() += 1;
+        if !json {
+            println!();
+        }
+    }
 
-    if let Some(pay) = payload {
-        println!("\n{}", pay);
+    // Keeps stdout pure JSON lines with --json.
+    let mut summary = format!("{} packets received", received);
+    for (bits, n) in &seen {
+        match bits {
+            Some(bits) => {
+                summary += &format!("\n    {:>6}  {}", n, TcpFlags::from_bits_retain(*bits))
+            }
+            None => summary += &format!("\n    {:>6}  (not TCP or malformed)", n),
+        }
+    }
+    if json {
+        eprintln!("{}", summary);
+    } else {
+        println!("{}", summary);
     }
 
     Ok(())
 }
+
+/// Leaves the filtering by protocol and destination port to the kernel.
+#[cfg(target_os = "linux")]
+fn attach_filter(receiver: &RawTcpSocket, protocol: Option<u8>, port: Option<u16>) {
+    receiver
+        .attach_filter(&harbinger::bpf::compile(protocol, port))
+        .unwrap_or_else(|e| panic!("Failed to attach the packet filter: {}", e));
+}
+
+#[cfg(not(target_os = "linux"))]
+fn attach_filter(_: &RawTcpSocket, _: Option<u8>, _: Option<u16>) {
+    panic!("--proto and --port filter in the kernel, which needs Linux");
+}
+
+/// Sets `INTERRUPTED` on Ctrl-C, from a thread running a small Tokio
+/// runtime for the signal handling.
+fn handle_ctrl_c() {
+    std::thread::spawn(|| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .expect("Failed to start the Ctrl-C handler");
+        if runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+            INTERRUPTED.store(true, Ordering::Relaxed);
+        }
+    });
+}
+
+/// One direction of a connection: source and destination address, then
+/// source and destination port.
+type Direction = (Ipv4Addr, Ipv4Addr, u16, u16);
+
+/// The window scale shifts seen on SYNs, per direction, since a window is
+/// meaningless without the shift its sender advertised on its SYN.
+#[derive(Default)]
+struct WindowScales(HashMap<Direction, u8>);
+
+impl WindowScales {
+    /// Remembers the shift on a SYN, returning the one to scale `tcp`'s
+    /// window by. Windows on SYNs are never scaled, and later ones only once
+    /// the SYNs of both directions carried the option (RFC 7323).
+    fn observe(&mut self, ip: &Ipv4Header, tcp: &Tcp) -> Option<u8> {
+        let direction = (ip.src, ip.dst, tcp.source_port, tcp.dest_port);
+        let reverse = (ip.dst, ip.src, tcp.dest_port, tcp.source_port);
+        if tcp.flags.contains(TcpFlags::SYN) {
+            match tcp.window_scale() {
+                Some(shift) => self.0.insert(direction, shift),
+                None => self.0.remove(&direction),
+            };
+            return None;
+        }
+
+        let shift = self
+            .0
+            .get(&direction)
+            .filter(|_| self.0.contains_key(&reverse))
+            .copied();
+        // The connection is gone; a new one starts with new SYNs.
+        if tcp.flags.contains(TcpFlags::RST) {
+            self.0.remove(&direction);
+            self.0.remove(&reverse);
+        }
+        shift
+    }
+}
+
+/// Prints one received datagram, returning its TCP flags if it was a TCP
+/// segment that parsed.
+fn handle_packet(
+    recieved_data: &[u8],
+    sender_addr: SocketAddr,
+    hex: bool,
+    json: bool,
+    scales: &mut WindowScales,
+) -> Option<TcpFlags> {
+    let bytes_read = recieved_data.len();
+
+    // Checks the IHL before anything is sliced with it.
+    let (ip, ip_options, tcp_data) = match Ipv4Header::parse_packet_raw(recieved_data) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!(
+                "Dropping {} bytes from {:?}: {}",
+                bytes_read, sender_addr, e
+            );
+            return None;
+        }
+    };
+    let ip_checksum_ok = verify_header_checksum(&recieved_data[..ip.header_len()]);
+    if !json {
+        println!(
+            "Recieved {} bytes from {:?}: IPv4 {} > {}, TTL {}, protocol {}, checksum {}",
+            bytes_read,
+            sender_addr,
+            ip.src,
+            ip.dst,
+            ip.ttl,
+            ip.protocol,
+            if ip_checksum_ok { "OK" } else { "BAD" }
+        );
+        if !ip_options.is_empty() {
+            println!(
+                "    IP options ({} bytes): {:02x?}",
+                ip_options.len(),
+                ip_options
+            );
+        }
+    }
+
+    if ip.protocol != PROTOCOL_TCP {
+        eprintln!("Skipping non-TCP datagram (protocol {})", ip.protocol);
+        return None;
+    }
+
+    match Tcp::parse_packet_raw(tcp_data) {
+        Ok((tcp, payload)) => {
+            let checksum_ok = tcp.verify_checksum(ip.src, ip.dst, payload);
+            let shift = scales.observe(&ip, &tcp);
+            #[cfg(feature = "serde")]
+            if json {
+                let window = shift.map(|shift| tcp.scaled_window(shift));
+                print_json(&ip, &tcp, checksum_ok, window, payload);
+                return Some(tcp.flags);
+            }
+
+            println!("{}", tcp);
+            println!("    Checksum: {}", if checksum_ok { "OK" } else { "BAD" });
+            if let Some(shift) = shift {
+                println!(
+                    "    Effective Window: {} ({} << {})",
+                    tcp.scaled_window(shift),
+                    tcp.window_size,
+                    shift
+                );
+            }
+
+            if hex {
+                // Dump the header and payload separately, so the data offset
+                // is where one ends and the other begins.
+                let data_offset = tcp_data.len() - payload.len();
+                println!("\nTCP header ({} bytes):", data_offset);
+                print!("{}", hexdump_at(&tcp_data[..data_offset], 0));
+                if !payload.is_empty() {
+                    println!("Payload ({} bytes):", payload.len());
+                    print!("{}", hexdump_at(payload, data_offset));
+                }
+            } else if !payload.is_empty() {
+                println!("\n{}", String::from_utf8_lossy(payload));
+            }
+            Some(tcp.flags)
+        }
+        Err(e) => {
+            eprintln!(
+                "Dropping {} bytes from {:?}: {}",
+                bytes_read, sender_addr, e
+            );
+            None
+        }
+    }
+}
+
+/// Prints the segment as one line of JSON: the `Tcp` fields (flags as an
+/// array of names), the addresses from the IP header, whether the checksum
+/// is right, the scaled window when the scale is known, and the payload in
+/// base64.
+#[cfg(feature = "serde")]
+fn print_json(
+    ip: &Ipv4Header,
+    tcp: &Tcp,
+    checksum_ok: bool,
+    effective_window: Option<u32>,
+    payload: &[u8],
+) {
+    let mut packet = serde_json::to_value(tcp).expect("Tcp serializes to JSON");
+    let fields = packet
+        .as_object_mut()
+        .expect("Tcp serializes to a JSON object");
+    fields.insert("src_ip".into(), ip.src.to_string().into());
+    fields.insert("dst_ip".into(), ip.dst.to_string().into());
+    fields.insert("checksum_ok".into(), checksum_ok.into());
+    if let Some(window) = effective_window {
+        fields.insert("effective_window".into(), window.into());
+    }
+    fields.insert(
+        "payload".into(),
+        harbinger::hexdump::encode_base64(payload).into(),
+    );
+    println!("{}", packet);
+}
@@ -0,0 +1,100 @@
+use harbinger::{
+    flags::TcpFlags,
+    handshake::{connection_seq, wait_for_reply},
+    raw::{source_ip_for, RawTcpSocket},
+    tcp::Tcp,
+};
+use std::{io, net::Ipv4Addr, ops::RangeInclusive, time::Duration};
+
+const USAGE: &str = "Usage: scanner <target-ip> <port>[-<port>] [--timeout <ms>]";
+
+/// Source port for the probes, in the ephemeral range.
+const SOURCE_PORT: u16 = 54321;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PortState {
+    /// Answered with a SYN-ACK.
+    Open,
+    /// Answered with a RST.
+    Closed,
+    /// No answer before the timeout.
+    Filtered,
+}
+
+/// SYN scan: sends a SYN to each port and classifies the answer, without
+/// ever completing a handshake. The kernel resets the half-open connections
+/// on its own, as it knows nothing about them.
+fn main() -> io::Result<()> {
+    let mut timeout = Duration::from_millis(1000);
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                let ms = args
+                    .next()
+                    .and_then(|ms| ms.parse().ok())
+                    .unwrap_or_else(|| panic!("--timeout expects milliseconds\n{}", USAGE));
+                timeout = Duration::from_millis(ms);
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let [target, ports] = &positional[..] else {
+        panic!("{}", USAGE);
+    };
+    let target: Ipv4Addr = target
+        .parse()
+        .unwrap_or_else(|e| panic!("Bad target IP {}: {}\n{}", target, e, USAGE));
+    let ports = parse_ports(ports).unwrap_or_else(|| panic!("Bad port range {}\n{}", ports, USAGE));
+
+    let src = source_ip_for(target)?;
+    let socket = RawTcpSocket::new()?;
+
+    let mut results = Vec::new();
+    for port in ports {
+        let mut builder = Tcp::builder();
+        builder
+            .source_port(SOURCE_PORT)
+            .dest_port(port)
+            .seq_num(connection_seq(src, SOURCE_PORT, target, port))
+            .flags(TcpFlags::SYN)
+            .mss(1460);
+        for warning in builder.warnings() {
+            eprintln!("Warning: {}", warning);
+        }
+        let syn = builder.build(src, target, b"");
+        socket.send(&syn.build_packet(b""), target)?;
+
+        let state = match wait_for_reply(&socket, &syn, target, timeout) {
+            Ok(reply) if reply.is_syn_ack() => PortState::Open,
+            Ok(_) => PortState::Closed,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => PortState::Filtered,
+            Err(e) => return Err(e),
+        };
+        results.push((port, state));
+    }
+
+    println!("Scan of {} from {}:\n", target, src);
+    println!("{:>7}  STATE", "PORT");
+    for (port, state) in &results {
+        println!("{:>7}  {:?}", port, state);
+    }
+
+    let count = |wanted| results.iter().filter(|(_, s)| *s == wanted).count();
+    println!(
+        "\n{} open, {} closed, {} filtered",
+        count(PortState::Open),
+        count(PortState::Closed),
+        count(PortState::Filtered)
+    );
+    Ok(())
+}
+
+/// Parses `80` or `20-25`.
+fn parse_ports(ports: &str) -> Option<RangeInclusive<u16>> {
+    let (first, last) = ports.split_once('-').unwrap_or((ports, ports));
+    let range = first.parse().ok()?..=last.parse().ok()?;
+    (!range.is_empty()).then_some(range)
+}
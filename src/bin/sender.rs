@@ -1,30 +1,190 @@
-use harbinger::{flags::TcpFlags, tcp::TcpBuilder};
-use socket2::{Domain, SockAddr, Socket, Type};
-use std::net::Ipv4Addr;
+use harbinger::{
+    checksum::PROTOCOL_TCP,
+    congestion::Sender,
+    flags::TcpFlags,
+    ip::{build_ipv4_tcp, Ipv4Header},
+    raw::RawTcpSocket,
+    retransmit::RetransmitQueue,
+    tcp::{PseudoHeader, Tcp, TcpBuilder},
+};
+use std::{
+    net::Ipv4Addr,
+    thread,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "Usage: sender [--segments <n>] [--loss-every <k>] [--packet-hex <hex>]";
+
+/// Round trip time assumed when pacing a stream, as nothing acknowledges it.
+const SIMULATED_RTT: Duration = Duration::from_millis(100);
 
 fn main() {
-    let sender =
-        Socket::new(Domain::IPV4, Type::RAW, None).expect("Failed to create sender socket");
+    let mut segments = None;
+    let mut loss_every = None;
+    let mut packet_hex = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || -> u32 {
+            args.next()
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or_else(|| panic!("{} expects a positive number\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--segments" => segments = Some(number()),
+            "--loss-every" => loss_every = Some(number()),
+            "--packet-hex" => {
+                packet_hex = Some(
+                    args.next()
+                        .unwrap_or_else(|| panic!("--packet-hex expects a segment\n{}", USAGE)),
+                )
+            }
+            _ => panic!("Unknown argument: {}\n{}", arg, USAGE),
+        }
+    }
+
+    let sender = RawTcpSocket::with_ip_header()
+        .unwrap_or_else(|e| panic!("Failed to create sender socket: {}", e));
     let src_ip = Ipv4Addr::new(127, 0, 0, 1);
     // Set the target IP address.
     let target_ip = Ipv4Addr::new(127, 0, 0, 1);
-    let target_sock_addr = SockAddr::from(std::net::SocketAddr::new(target_ip.into(), 0));
 
-    let payload = b"Hello, TCP!";
-    let tcp = TcpBuilder::new()
-        .source_port(0)
+    if let Some(segments) = segments {
+        send_stream(&sender, src_ip, target_ip, segments, loss_every);
+        return;
+    }
+
+    if let Some(hex) = packet_hex {
+        let (mut tcp, payload) =
+            Tcp::from_hex(&hex).unwrap_or_else(|e| panic!("Invalid --packet-hex segment: {}", e));
+        // The segment was captured between other addresses.
+        tcp.checksum = 0;
+        tcp.checksum = tcp.calculate_checksum_with(
+            PseudoHeader::V4 {
+                src: src_ip,
+                dst: target_ip,
+            },
+            &payload,
+        );
+        let ip = Ipv4Header::new(src_ip, target_ip, PROTOCOL_TCP);
+        sender
+            .send(&build_ipv4_tcp(ip, &tcp, &payload), target_ip)
+            .unwrap_or_else(|e| panic!("Failed to send to addr: {:?},\n{}", target_ip, e));
+        return;
+    }
+
+    // Construct a raw payload (custom protocol, 0xABCD, for example)
+    let payload = b"hello, raw TCP!";
+    let mut builder = Tcp::builder();
+    builder
+        .ephemeral_source_port()
         .dest_port(0)
         .seq_num(305419896)
         .ack_num(2271560481)
         .flags(TcpFlags::SYN)
         .window_size(255)
-        .build(src_ip, target_ip, payload);
+        .payload(payload);
+    warn(&builder);
+    let tcp = builder.build(src_ip, target_ip, payload);
 
-    // Construct a raw payload (custom protocol, 0xABCD, for example)
-    let payload = b"hello, raw TCP!";
-    let packet = tcp.build_packet(payload);
+    // The IP header is built here too, so the datagram is the same on Linux
+    // and macOS.
+    let ip = Ipv4Header::new(src_ip, target_ip, PROTOCOL_TCP);
+    let packet = build_ipv4_tcp(ip, &tcp, payload);
 
     sender
-        .send_to(&packet, &target_sock_addr)
-        .unwrap_or_else(|e| panic!("Failed to send to addr: {:?},\n{}", target_sock_addr, e));
+        .send(&packet, target_ip)
+        .unwrap_or_else(|e| panic!("Failed to send to addr: {:?},\n{}", target_ip, e));
+}
+
+/// Sends `segments` data segments, as many per simulated round trip as the
+/// congestion window allows. Every segment of a round is then taken as
+/// acknowledged, except every `loss_every`th one, which counts as lost and
+/// is retransmitted once its timer fires.
+fn send_stream(
+    sender: &RawTcpSocket,
+    src_ip: Ipv4Addr,
+    target_ip: Ipv4Addr,
+    segments: u32,
+    loss_every: Option<u32>,
+) {
+    let payload = b"hello, raw TCP!";
+    let len = payload.len() as u32;
+    let mut window = Sender::new(payload.len() as u16);
+    let mut queue = RetransmitQueue::new();
+    let mut seq_num: u32 = 305419896;
+    let mut sent = 0;
+
+    // Every segment of the stream comes from the same port.
+    let mut segment = Tcp::builder();
+    segment
+        .ephemeral_source_port()
+        .dest_port(0)
+        .ack_num(2271560481)
+        .flags(TcpFlags::PSH_ACK)
+        .window_size(255);
+    warn(&segment);
+
+    let send = |packet: &[u8]| {
+        sender
+            .send(packet, target_ip)
+            .unwrap_or_else(|e| panic!("Failed to send to addr: {:?},\n{}", target_ip, e));
+    };
+
+    while sent < segments {
+        let mut round = Vec::new();
+        while sent < segments && window.can_send(len) {
+            let tcp = segment.seq_num(seq_num).build(src_ip, target_ip, payload);
+            let ip = Ipv4Header::new(src_ip, target_ip, PROTOCOL_TCP);
+            let packet = build_ipv4_tcp(ip, &tcp, payload);
+            send(&packet);
+
+            queue.push(seq_num, len, packet, Instant::now());
+            window.on_send(len);
+            round.push(seq_num);
+            seq_num = seq_num.wrapping_add(len);
+            sent += 1;
+        }
+
+        println!(
+            "Sent {} segments, cwnd {} bytes, ssthresh {}",
+            round.len(),
+            window.cwnd(),
+            window.ssthresh()
+        );
+        thread::sleep(SIMULATED_RTT);
+
+        let first = sent - round.len() as u32;
+        let lost = loss_every.and_then(|k| {
+            (first..sent)
+                .position(|i| (i + 1) % k == 0)
+                .map(|i| round[i])
+        });
+        if let Some(lost) = lost {
+            // The peer's cumulative ACK stops at the hole.
+            queue.on_ack(lost, Instant::now());
+            println!("Simulated loss of seq {}, halving the window", lost);
+            window.on_loss();
+
+            if let Some(expiry) = queue.next_expiry() {
+                thread::sleep(expiry.saturating_duration_since(Instant::now()));
+            }
+            if let Some(packet) = queue.resend_expired(Instant::now()) {
+                send(packet);
+                println!("Retransmitted seq {}, RTO now {:?}", lost, queue.rto());
+            }
+        }
+
+        queue.on_ack(seq_num, Instant::now());
+        for _ in 0..round.len() {
+            window.on_ack(len);
+        }
+    }
+}
+
+/// Prints what `builder.warnings()` reports before the segment goes out.
+fn warn(builder: &TcpBuilder) {
+    for warning in builder.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
 }
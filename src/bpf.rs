@@ -0,0 +1,203 @@
+use crate::checksum::PROTOCOL_TCP;
+use alloc::vec::Vec;
+
+/// One classic BPF instruction, laid out like Linux's `struct sock_filter`.
+///
+/// Programs run over each datagram a raw socket receives, starting at its
+/// IPv4 header, and return how many of its bytes to keep: 0 drops it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BpfInstruction {
+    pub code: u16,
+    /// Instructions to skip when a conditional jump is taken.
+    pub jt: u8,
+    /// Instructions to skip when it isn't.
+    pub jf: u8,
+    pub k: u32,
+}
+
+// Opcodes from <linux/filter.h>, combined as class | size | mode.
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MSH: u16 = 0xa0;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JSET: u16 = 0x40;
+const BPF_K: u16 = 0x00;
+
+/// What an accepted datagram keeps: all of it.
+const ACCEPT_LEN: u32 = 65535;
+
+/// A step of the filter before it accepts, every check jumping to the
+/// final reject on failure.
+enum Step {
+    Load(u16, u32),
+    RejectUnlessEqual(u32),
+    RejectIfAnySet(u32),
+}
+
+/// Compiles a filter keeping only datagrams with IP protocol `protocol` and
+/// destination port `dst_port`, either left out to match any. The port is
+/// read where TCP and UDP keep it, so it's meant for those two; fragments
+/// after the first, which carry no transport header, never match it.
+///
+/// Attach the result with `RawTcpSocket::attach_filter` so the kernel drops
+/// the rest before they're copied to userspace.
+pub fn compile(protocol: Option<u8>, dst_port: Option<u16>) -> Vec<BpfInstruction> {
+    let mut steps = Vec::new();
+    if let Some(protocol) = protocol {
+        steps.push(Step::Load(BPF_LD | BPF_B | BPF_ABS, 9));
+        steps.push(Step::RejectUnlessEqual(protocol as u32));
+    }
+    if let Some(port) = dst_port {
+        // Flags and fragment offset; a non-zero offset has no ports.
+        steps.push(Step::Load(BPF_LD | BPF_H | BPF_ABS, 6));
+        steps.push(Step::RejectIfAnySet(0x1fff));
+        // X = 4 * IHL, where the transport header starts.
+        steps.push(Step::Load(BPF_LDX | BPF_B | BPF_MSH, 0));
+        steps.push(Step::Load(BPF_LD | BPF_H | BPF_IND, 2));
+        steps.push(Step::RejectUnlessEqual(port as u32));
+    }
+
+    // The accept and reject returns follow the steps, so a failed check at
+    // `i` skips the rest of the steps and the accept.
+    let count = steps.len();
+    let mut program: Vec<_> = steps
+        .into_iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let to_reject = (count - i) as u8;
+            match step {
+                Step::Load(code, k) => BpfInstruction {
+                    code,
+                    jt: 0,
+                    jf: 0,
+                    k,
+                },
+                Step::RejectUnlessEqual(k) => BpfInstruction {
+                    code: BPF_JMP | BPF_JEQ | BPF_K,
+                    jt: 0,
+                    jf: to_reject,
+                    k,
+                },
+                Step::RejectIfAnySet(k) => BpfInstruction {
+                    code: BPF_JMP | BPF_JSET | BPF_K,
+                    jt: to_reject,
+                    jf: 0,
+                    k,
+                },
+            }
+        })
+        .collect();
+    program.push(ret(ACCEPT_LEN));
+    program.push(ret(0));
+    program
+}
+
+/// The filter for "tcp and dst port `port`".
+pub fn tcp_dst_port(port: u16) -> Vec<BpfInstruction> {
+    compile(Some(PROTOCOL_TCP), Some(port))
+}
+
+fn ret(k: u32) -> BpfInstruction {
+    BpfInstruction {
+        code: BPF_RET | BPF_K,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::checksum::PROTOCOL_UDP;
+    use crate::ip::{build_ipv4_tcp, Ipv4Header};
+    use crate::tcp::Tcp;
+    use core::net::Ipv4Addr;
+
+    /// Runs `program` over `packet` the way the kernel would, for the
+    /// instructions `compile` emits.
+    fn run(program: &[BpfInstruction], packet: &[u8]) -> u32 {
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0);
+        loop {
+            let insn = program[pc];
+            pc += 1;
+            let at = |offset: usize, len: usize| packet.get(offset..offset + len);
+            match insn.code {
+                0x30 => match at(insn.k as usize, 1) {
+                    Some(b) => a = b[0] as u32,
+                    None => return 0,
+                },
+                0x28 | 0x48 => {
+                    let base = if insn.code == 0x48 { x as usize } else { 0 };
+                    match at(base + insn.k as usize, 2) {
+                        Some(h) => a = u16::from_be_bytes([h[0], h[1]]) as u32,
+                        None => return 0,
+                    }
+                }
+                0xb1 => match at(insn.k as usize, 1) {
+                    Some(b) => x = 4 * (b[0] & 0x0f) as u32,
+                    None => return 0,
+                },
+                0x15 | 0x45 => {
+                    let taken = if insn.code == 0x15 {
+                        a == insn.k
+                    } else {
+                        a & insn.k != 0
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+                0x06 => return insn.k,
+                code => panic!("unexpected opcode {:#x}", code),
+            }
+        }
+    }
+
+    fn tcp_packet(dst_port: u16) -> Vec<u8> {
+        let ip = Ipv4Addr::LOCALHOST;
+        let tcp = Tcp::builder()
+            .source_port(49152)
+            .dest_port(dst_port)
+            .build(ip, ip, b"");
+        build_ipv4_tcp(Ipv4Header::new(ip, ip, PROTOCOL_TCP), &tcp, b"")
+    }
+
+    #[test]
+    fn test_bpf_tcp_dst_port() {
+        let program = tcp_dst_port(8080);
+        assert_eq!(program.len(), 9);
+        assert_eq!(run(&program, &tcp_packet(8080)), ACCEPT_LEN);
+        assert_eq!(run(&program, &tcp_packet(80)), 0);
+
+        // The same port over UDP.
+        let mut udp = tcp_packet(8080);
+        udp[9] = PROTOCOL_UDP;
+        assert_eq!(run(&program, &udp), 0);
+        assert_eq!(run(&compile(None, Some(8080)), &udp), ACCEPT_LEN);
+
+        // A later fragment, whose payload only looks like a header.
+        let mut fragment = tcp_packet(8080);
+        fragment[7] = 1;
+        assert_eq!(run(&program, &fragment), 0);
+
+        // Finds the ports past IP options.
+        let mut with_options = tcp_packet(8080);
+        with_options[0] = 0x46;
+        with_options.splice(20..20, [1, 1, 1, 0]);
+        assert_eq!(run(&program, &with_options), ACCEPT_LEN);
+    }
+
+    #[test]
+    fn test_bpf_compile_without_checks_accepts() {
+        let program = compile(None, None);
+        assert_eq!(program, [ret(ACCEPT_LEN), ret(0)]);
+        assert_eq!(run(&program, &tcp_packet(1)), ACCEPT_LEN);
+        assert_eq!(run(&compile(Some(PROTOCOL_UDP), None), &tcp_packet(1)), 0);
+    }
+}
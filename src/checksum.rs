@@ -0,0 +1,199 @@
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+
+/// The addresses the TCP and UDP checksums cover through a pseudo-header
+/// conceptually prefixed to the transport header.
+///
+/// The IPv4 pseudo-header is 96 bits (12 bytes, 4 per row):
+///
+/// ```text
+///   +--------+--------+--------+--------+
+///   |           Source Address          |
+///   +--------+--------+--------+--------+
+///   |         Destination Address       |
+///   +--------+--------+--------+--------+
+///   |  zero  |  PTCL  |  TCP/UDP Length |
+///   +--------+--------+--------+--------+
+/// ```
+///
+/// The IPv6 pseudo-header is 320 bits (RFC 8200, section 8.1):
+///
+/// ```text
+///   +--------+--------+--------+--------+
+///   |                                   |
+///   +         Source Address (16)       +
+///   |                                   |
+///   +--------+--------+--------+--------+
+///   |                                   |
+///   +      Destination Address (16)     +
+///   |                                   |
+///   +--------+--------+--------+--------+
+///   |   TCP/UDP Length (32 bits)        |
+///   +--------+--------+--------+--------+
+///   |          zero            |  NH    |
+///   +--------+--------+--------+--------+
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum PseudoHeader {
+    V4 { src: Ipv4Addr, dst: Ipv4Addr },
+    V6 { src: Ipv6Addr, dst: Ipv6Addr },
+}
+
+impl PseudoHeader {
+    /// Sums the pseudo-header's 16-bit words for a `protocol` segment of
+    /// `length` bytes, without folding.
+    pub(crate) fn sum(&self, protocol: u8, length: u32) -> u32 {
+        let mut sum = 0u32;
+        match self {
+            PseudoHeader::V4 { src, dst } => {
+                for chunk in src.octets().chunks(2).chain(dst.octets().chunks(2)) {
+                    sum += u16::from_be_bytes(chunk.try_into().unwrap()) as u32;
+                }
+                // Reserved (0), Protocol number, 16-bit Length
+                sum += protocol as u32;
+                sum += length & 0xFFFF;
+            }
+            PseudoHeader::V6 { src, dst } => {
+                for chunk in src.octets().chunks(2).chain(dst.octets().chunks(2)) {
+                    sum += u16::from_be_bytes(chunk.try_into().unwrap()) as u32;
+                }
+                // 32-bit Length, 24 zero bits, Next Header
+                sum += length >> 16;
+                sum += length & 0xFFFF;
+                sum += protocol as u32;
+            }
+        }
+        sum
+    }
+}
+
+/// Returns the ones' complement sum of `data` taken as big-endian 16-bit
+/// words, folded to 16 bits (RFC 1071). The checksum is its complement.
+///
+/// An odd trailing octet is padded with zero on its right to form a word.
+pub fn ones_complement_sum(data: &[u8]) -> u16 {
+    // Accumulate in 64 bits so arbitrarily long input can't overflow before
+    // the carries are folded back in.
+    let mut sum = 0u64;
+    for chunk in data.chunks(2) {
+        if chunk.len() == 2 {
+            sum += u16::from_be_bytes(chunk.try_into().unwrap()) as u64;
+        } else {
+            sum += (chunk[0] as u64) << 8;
+        }
+    }
+
+    while (sum >> 32) > 0 {
+        sum = (sum & 0xFFFF_FFFF) + (sum >> 32);
+    }
+    fold(sum as u32)
+}
+
+/// Fold 32-bit sum into 16-bit.
+///
+/// If the addition of the high and low 16 bits produces any carry-out,
+/// (i.e. the new sum exceeds 16 bits) the process is repetead till no
+/// carry-out.
+/// perseving the mathematical correctness of one's complement by
+/// adding any carry-out back the the lower 16 bits.
+pub fn fold(mut sum: u32) -> u16 {
+    while (sum >> 16) > 0 {
+        // `sum & 0xFFFF` extract the low 16 bits of sum.
+        // `sum >> 16` extracts the high 16 bits of sum,
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Updates `checksum` for a 16-bit word of the covered data changing from
+/// `old` to `new`, without touching the rest of the data.
+///
+/// RFC 1624, eqn. 3: `HC' = ~(~HC + ~m + m')`. Unlike the RFC 1141 form it
+/// never yields a -0 (`0xFFFF`) checksum for a non-zero sum.
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    let sum = (!checksum) as u32 + (!old) as u32 + new as u32;
+    !fold(sum)
+}
+
+/// `update` for an IPv4 address changing from `old` to `new`, as its two
+/// words. The address is in the IPv4 header and in the TCP and UDP
+/// pseudo-headers, so NAT has to do this for every layer.
+pub fn update_address(checksum: u16, old: Ipv4Addr, new: Ipv4Addr) -> u16 {
+    let (old, new) = (old.octets(), new.octets());
+    let checksum = update(
+        checksum,
+        u16::from_be_bytes([old[0], old[1]]),
+        u16::from_be_bytes([new[0], new[1]]),
+    );
+    update(
+        checksum,
+        u16::from_be_bytes([old[2], old[3]]),
+        u16::from_be_bytes([new[2], new[3]]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_fold_carries() {
+        assert_eq!(fold(0x0001_FFFF), 0x0001);
+        assert_eq!(fold(0x0002_FFFE), 0x0001);
+        assert_eq!(fold(0x1234), 0x1234);
+    }
+
+    #[test]
+    fn test_ones_complement_sum_rfc1071() {
+        // RFC 1071, section 3: the sum of 00 01 f2 03 f4 f5 f6 f7 is ddf2,
+        // giving a checksum of 220d.
+        let data = [0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7];
+        assert_eq!(ones_complement_sum(&data), 0xDDF2);
+        assert_eq!(!ones_complement_sum(&data), 0x220D);
+    }
+
+    #[test]
+    fn test_ones_complement_sum_byte_order_independence() {
+        // RFC 1071, section 2(B): swapping the bytes of every word swaps the
+        // bytes of the sum.
+        let data = [0x01, 0x00, 0x03, 0xF2, 0xF5, 0xF4, 0xF7, 0xF6];
+        assert_eq!(ones_complement_sum(&data), 0xF2DD);
+    }
+
+    #[test]
+    fn test_ones_complement_sum_odd_tail() {
+        assert_eq!(ones_complement_sum(&[0x12, 0x34, 0x56]), 0x1234 + 0x5600);
+        assert_eq!(ones_complement_sum(&[]), 0);
+    }
+
+    #[test]
+    fn test_ones_complement_sum_long_input() {
+        // 2^17 words of ffff would overflow a 32-bit accumulator.
+        assert_eq!(ones_complement_sum(&[0xFF; 1 << 18]), 0xFFFF);
+    }
+
+    #[test]
+    fn test_update_matches_recompute() {
+        let mut data = [0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7];
+        let checksum = !ones_complement_sum(&data);
+
+        data[2..4].copy_from_slice(&0x1234u16.to_be_bytes());
+        assert_eq!(
+            update(checksum, 0xF203, 0x1234),
+            !ones_complement_sum(&data)
+        );
+    }
+
+    #[test]
+    fn test_pseudo_header_protocol() {
+        let ph = PseudoHeader::V4 {
+            src: Ipv4Addr::new(0, 0, 0, 0),
+            dst: Ipv4Addr::new(0, 0, 0, 0),
+        };
+        assert_eq!(ph.sum(PROTOCOL_UDP, 8), 17 + 8);
+    }
+}
@@ -0,0 +1,146 @@
+//! Slow start and congestion avoidance (RFC 5681), without fast recovery.
+//!
+//! While `cwnd` is below `ssthresh` every ACK grows the window by one MSS,
+//! doubling it each round trip. Past `ssthresh` it grows by about one MSS
+//! per round trip. A loss halves the window.
+
+/// `ssthresh` starts "arbitrarily high" (RFC 5681), here the largest
+/// unscaled window.
+const INITIAL_SSTHRESH: u32 = 65535;
+
+/// Congestion control state for the sending side of a connection, deciding
+/// how many bytes may be in flight.
+#[derive(Debug, Clone)]
+pub struct Sender {
+    mss: u32,
+    cwnd: u32,
+    ssthresh: u32,
+    in_flight: u32,
+}
+
+impl Sender {
+    /// Starts in slow start with a window of one segment.
+    pub fn new(mss: u16) -> Self {
+        let mss = mss.max(1) as u32;
+        Self {
+            mss,
+            cwnd: mss,
+            ssthresh: INITIAL_SSTHRESH,
+            in_flight: 0,
+        }
+    }
+
+    /// Congestion window in bytes.
+    pub fn cwnd(&self) -> u32 {
+        self.cwnd
+    }
+
+    pub fn ssthresh(&self) -> u32 {
+        self.ssthresh
+    }
+
+    /// Bytes sent but not acknowledged yet.
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight
+    }
+
+    pub fn in_slow_start(&self) -> bool {
+        self.cwnd < self.ssthresh
+    }
+
+    /// Whether `len` more bytes fit in the window.
+    pub fn can_send(&self, len: u32) -> bool {
+        self.in_flight.saturating_add(len) <= self.cwnd
+    }
+
+    pub fn on_send(&mut self, len: u32) {
+        self.in_flight = self.in_flight.saturating_add(len);
+    }
+
+    /// An ACK for `acked` new bytes arrived.
+    pub fn on_ack(&mut self, acked: u32) {
+        self.in_flight = self.in_flight.saturating_sub(acked);
+
+        let growth = if self.in_slow_start() {
+            // At most one MSS per ACK, as RFC 5681 recommends.
+            acked.min(self.mss)
+        } else {
+            // MSS * MSS / cwnd per ACK, about one MSS per round trip.
+            (self.mss * self.mss / self.cwnd).max(1)
+        };
+        self.cwnd = self.cwnd.saturating_add(growth);
+    }
+
+    /// A segment was lost: halve the window and leave slow start.
+    pub fn on_loss(&mut self) {
+        self.ssthresh = (self.in_flight.max(self.cwnd) / 2).max(2 * self.mss);
+        self.cwnd = self.ssthresh;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Sends a full window, then acknowledges each segment of it.
+    fn round_trip(sender: &mut Sender) {
+        let mss = sender.mss;
+        let mut sent = 0;
+        while sender.can_send(mss) {
+            sender.on_send(mss);
+            sent += 1;
+        }
+        for _ in 0..sent {
+            sender.on_ack(mss);
+        }
+    }
+
+    #[test]
+    fn test_slow_start_doubles_each_round_trip() {
+        let mut sender = Sender::new(1000);
+        let mut windows = vec![sender.cwnd()];
+        for _ in 0..4 {
+            round_trip(&mut sender);
+            windows.push(sender.cwnd());
+        }
+        assert_eq!(windows, [1000, 2000, 4000, 8000, 16000]);
+        assert_eq!(sender.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_congestion_avoidance_is_linear() {
+        let mut sender = Sender::new(1000);
+        sender.on_send(20000);
+        sender.on_loss();
+        assert_eq!((sender.cwnd(), sender.ssthresh()), (10000, 10000));
+        assert!(!sender.in_slow_start());
+        sender.on_ack(20000);
+
+        // cwnd is now just above ssthresh, each round trip adds about an MSS.
+        let mut previous = sender.cwnd();
+        for _ in 0..5 {
+            round_trip(&mut sender);
+            let growth = sender.cwnd() - previous;
+            assert!((800..=1000).contains(&growth), "grew by {}", growth);
+            previous = sender.cwnd();
+        }
+    }
+
+    #[test]
+    fn test_loss_halves_window() {
+        let mut sender = Sender::new(1000);
+        for _ in 0..3 {
+            round_trip(&mut sender);
+        }
+        assert_eq!(sender.cwnd(), 8000);
+
+        sender.on_loss();
+        assert_eq!((sender.cwnd(), sender.ssthresh()), (4000, 4000));
+
+        // Never below two segments.
+        let mut sender = Sender::new(1000);
+        sender.on_loss();
+        assert_eq!(sender.cwnd(), 2000);
+    }
+}
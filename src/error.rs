@@ -0,0 +1,437 @@
+use crate::flags::TcpFlags;
+use crate::state::{ConnectionState, TcpEvent};
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Reasons a byte slice can't be decoded as a TCP header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpParseError {
+    /// Fewer than the 20 bytes of the fixed header were available.
+    TooShort { got: usize },
+    /// The flags byte has bits `TcpFlags` doesn't define. All 8 are
+    /// defined, so parsing never returns this.
+    #[deprecated(note = "never returned: every flags byte parses; see TcpFlags::has_unknown_bits")]
+    BadFlags(u8),
+    /// The data offset is below 5 words or claims more bytes than available.
+    BadDataOffset(u8),
+    /// The segment is longer than the parser's `max_segment_size`.
+    TooLong { got: usize, max: usize },
+    /// An option of this kind has a missing or impossible length byte.
+    BadOption(u8),
+}
+
+impl fmt::Display for TcpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpParseError::TooShort { got } => {
+                write!(f, "TCP header must be at least 20 bytes, received: {}", got)
+            }
+            #[allow(deprecated)]
+            TcpParseError::BadFlags(bits) => write!(f, "invalid TCP flags: {:#04x}", bits),
+            TcpParseError::BadDataOffset(offset) => {
+                write!(f, "invalid TCP data offset: {} words", offset)
+            }
+            TcpParseError::TooLong { got, max } => {
+                write!(f, "TCP segment is {} bytes, at most {} allowed", got, max)
+            }
+            TcpParseError::BadOption(kind) => {
+                write!(f, "invalid length for TCP option kind {}", kind)
+            }
+        }
+    }
+}
+
+impl core::error::Error for TcpParseError {}
+
+/// Reasons a byte slice can't be decoded as an IPv4 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv4ParseError {
+    /// Fewer than the 20 bytes of the fixed header were available.
+    TooShort { got: usize },
+    /// The version nibble isn't 4.
+    BadVersion(u8),
+    /// The IHL is below 5 words or claims more bytes than available.
+    BadIhl(u8),
+}
+
+impl fmt::Display for Ipv4ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ipv4ParseError::TooShort { got } => {
+                write!(
+                    f,
+                    "IPv4 header must be at least 20 bytes, received: {}",
+                    got
+                )
+            }
+            Ipv4ParseError::BadVersion(version) => {
+                write!(f, "not an IPv4 packet: version {}", version)
+            }
+            Ipv4ParseError::BadIhl(ihl) => write!(f, "invalid IPv4 header length: {} words", ihl),
+        }
+    }
+}
+
+impl core::error::Error for Ipv4ParseError {}
+
+/// An event the RFC 793 state diagram has no edge for in the current state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: ConnectionState,
+    pub event: TcpEvent,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid event {:?} in state {}", self.event, self.from)
+    }
+}
+
+impl core::error::Error for InvalidTransition {}
+
+/// Reasons a byte slice can't be decoded as a UDP header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdpParseError {
+    /// Fewer than the 8 bytes of the header were available.
+    TooShort { got: usize },
+    /// The length field is below 8 or claims more bytes than available.
+    BadLength(u16),
+}
+
+impl fmt::Display for UdpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpParseError::TooShort { got } => {
+                write!(f, "UDP header must be 8 bytes, received: {}", got)
+            }
+            UdpParseError::BadLength(length) => write!(f, "invalid UDP length: {}", length),
+        }
+    }
+}
+
+impl core::error::Error for UdpParseError {}
+
+/// Reasons a byte slice can't be decoded as an ICMP message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpParseError {
+    /// Fewer than the 8 bytes of the header were available.
+    TooShort { got: usize },
+    /// Only Destination Unreachable and Time Exceeded quote the datagram
+    /// they're about; this is the type of a message that doesn't.
+    NoOriginal(u8),
+    /// The quoted datagram's IP header doesn't parse.
+    Original(Ipv4ParseError),
+}
+
+impl fmt::Display for IcmpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcmpParseError::TooShort { got } => {
+                write!(f, "ICMP header must be 8 bytes, received: {}", got)
+            }
+            IcmpParseError::NoOriginal(type_) => {
+                write!(f, "ICMP type {} carries no original datagram", type_)
+            }
+            IcmpParseError::Original(e) => write!(f, "bad original datagram: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for IcmpParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            IcmpParseError::Original(e) => Some(e),
+            IcmpParseError::TooShort { .. } | IcmpParseError::NoOriginal(_) => None,
+        }
+    }
+}
+
+impl From<Ipv4ParseError> for IcmpParseError {
+    fn from(e: Ipv4ParseError) -> Self {
+        IcmpParseError::Original(e)
+    }
+}
+
+/// Reasons a byte slice can't be decoded as an Ethernet frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EthParseError {
+    /// Fewer than the 14 bytes of the header were available.
+    TooShort { got: usize },
+}
+
+impl fmt::Display for EthParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EthParseError::TooShort { got } => {
+                write!(f, "Ethernet header must be 14 bytes, received: {}", got)
+            }
+        }
+    }
+}
+
+impl core::error::Error for EthParseError {}
+
+/// Failures of `eth::parse_eth_ipv4_tcp`, which walks several layers, and
+/// of `Tcp::from_hex`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    Eth(EthParseError),
+    /// The frame carries something other than IPv4, by EtherType.
+    NotIpv4(u16),
+    Ipv4(Ipv4ParseError),
+    /// The datagram carries something other than TCP, by IP protocol.
+    NotTcp(u8),
+    Tcp(TcpParseError),
+    /// A character in a hex string that isn't a hex digit or separator.
+    BadHexDigit(char),
+    /// A hex string with an odd number of digits, which don't make whole
+    /// bytes.
+    OddHexLength(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Eth(e) => e.fmt(f),
+            ParseError::NotIpv4(ethertype) => {
+                write!(f, "not an IPv4 frame: EtherType {:#06x}", ethertype)
+            }
+            ParseError::Ipv4(e) => e.fmt(f),
+            ParseError::NotTcp(protocol) => {
+                write!(f, "not a TCP datagram: protocol {}", protocol)
+            }
+            ParseError::Tcp(e) => e.fmt(f),
+            ParseError::BadHexDigit(c) => write!(f, "invalid hex digit: {:?}", c),
+            ParseError::OddHexLength(len) => {
+                write!(f, "hex string has an odd number of digits: {}", len)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            ParseError::Eth(e) => Some(e),
+            ParseError::Ipv4(e) => Some(e),
+            ParseError::Tcp(e) => Some(e),
+            ParseError::NotIpv4(_)
+            | ParseError::NotTcp(_)
+            | ParseError::BadHexDigit(_)
+            | ParseError::OddHexLength(_) => None,
+        }
+    }
+}
+
+impl From<EthParseError> for ParseError {
+    fn from(e: EthParseError) -> Self {
+        ParseError::Eth(e)
+    }
+}
+
+impl From<Ipv4ParseError> for ParseError {
+    fn from(e: Ipv4ParseError) -> Self {
+        ParseError::Ipv4(e)
+    }
+}
+
+impl From<TcpParseError> for ParseError {
+    fn from(e: TcpParseError) -> Self {
+        ParseError::Tcp(e)
+    }
+}
+
+/// A name in a flags string that isn't a TCP flag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFlagsError {
+    pub token: String,
+}
+
+impl fmt::Display for ParseFlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown TCP flag: {:?}", self.token)
+    }
+}
+
+impl core::error::Error for ParseFlagsError {}
+
+/// A flag combination `TcpFlags::validate` finds can't appear on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagError {
+    /// SYN and FIN together: open and close in one segment.
+    SynFin,
+    /// SYN and RST together.
+    SynRst,
+    /// RST and FIN together.
+    RstFin,
+    /// No flags at all, as in a null scan. Every segment but the first SYN
+    /// carries at least ACK.
+    Null,
+    /// FIN without ACK, as in FIN and Xmas scans. A FIN comes after the
+    /// handshake, when every segment has ACK set.
+    FinWithoutAck,
+}
+
+impl FlagError {
+    /// The offending flags.
+    pub fn flags(&self) -> TcpFlags {
+        match self {
+            FlagError::SynFin => TcpFlags::SYN | TcpFlags::FIN,
+            FlagError::SynRst => TcpFlags::SYN | TcpFlags::RST,
+            FlagError::RstFin => TcpFlags::RST | TcpFlags::FIN,
+            FlagError::Null => TcpFlags::empty(),
+            FlagError::FinWithoutAck => TcpFlags::FIN,
+        }
+    }
+}
+
+impl fmt::Display for FlagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagError::SynFin => write!(f, "SYN and FIN can't be set together"),
+            FlagError::SynRst => write!(f, "SYN and RST can't be set together"),
+            FlagError::RstFin => write!(f, "RST and FIN can't be set together"),
+            FlagError::Null => write!(f, "no flags set"),
+            FlagError::FinWithoutAck => write!(f, "FIN set without ACK"),
+        }
+    }
+}
+
+impl core::error::Error for FlagError {}
+
+/// Header combinations `TcpBuilder::build_checked` refuses to produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// A flag combination no legitimate segment carries, as
+    /// `TcpFlags::validate` reports it.
+    Flags(FlagError),
+    /// ACK is set but the acknowledgment number is 0.
+    ZeroAckNum,
+    /// A window scale shift above the RFC 7323 limit of 14.
+    WindowScaleTooLarge(u8),
+    /// More SACK blocks than fit in the options region.
+    TooManySackBlocks(usize),
+    /// A SACK option without blocks, which RFC 2018 doesn't allow.
+    EmptySack,
+    /// The options need more than the 40 bytes the data offset can cover.
+    OptionsTooLong(usize),
+    /// The source port is outside the IANA ephemeral range (49152-65535)
+    /// while `TcpBuilder::ephemeral_source_only` is on.
+    NonEphemeralSourcePort(u16),
+    /// Reserved header bits are set.
+    NonZeroReserved(u8),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Flags(e) => e.fmt(f),
+            BuildError::ZeroAckNum => write!(f, "ACK is set but the ack number is 0"),
+            BuildError::WindowScaleTooLarge(shift) => {
+                write!(f, "window scale shift {} is above the limit of 14", shift)
+            }
+            BuildError::EmptySack => write!(f, "SACK option has no blocks"),
+            BuildError::TooManySackBlocks(count) => {
+                write!(f, "{} SACK blocks given, at most 4 fit", count)
+            }
+            BuildError::OptionsTooLong(len) => {
+                write!(f, "TCP options take {} bytes, at most 40 fit", len)
+            }
+            BuildError::NonEphemeralSourcePort(port) => {
+                write!(f, "source port {} is not in the ephemeral range", port)
+            }
+            BuildError::NonZeroReserved(bits) => {
+                write!(f, "reserved bits must be 0, got {:#05b}", bits)
+            }
+        }
+    }
+}
+
+/// Setups `TcpBuilder::build_checked` lets through that are almost always
+/// mistakes, as reported by `TcpBuilder::warnings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildWarning {
+    /// The source port is 0, which is reserved: no stack will answer it.
+    ZeroSourcePort,
+}
+
+impl fmt::Display for BuildWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildWarning::ZeroSourcePort => write!(f, "source port 0 is reserved"),
+        }
+    }
+}
+
+impl core::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            BuildError::Flags(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FlagError> for BuildError {
+    fn from(e: FlagError) -> Self {
+        BuildError::Flags(e)
+    }
+}
+
+/// Failures of `RawTcpSocket`, singling out the missing-privileges case.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum RawSocketError {
+    /// The OS refused the raw socket: it needs root or `CAP_NET_RAW`.
+    PermissionDenied(io::Error),
+    Io(io::Error),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for RawSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawSocketError::PermissionDenied(e) => write!(
+                f,
+                "{}: raw sockets need root or CAP_NET_RAW \
+                 (try sudo, or setcap cap_net_raw+ep on the binary)",
+                e
+            ),
+            RawSocketError::Io(e) => write!(f, "raw socket error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RawSocketError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RawSocketError::PermissionDenied(e) | RawSocketError::Io(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for RawSocketError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::PermissionDenied {
+            RawSocketError::PermissionDenied(e)
+        } else {
+            RawSocketError::Io(e)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<RawSocketError> for io::Error {
+    fn from(e: RawSocketError) -> Self {
+        match e {
+            RawSocketError::PermissionDenied(ref inner) => {
+                io::Error::new(inner.kind(), e.to_string())
+            }
+            RawSocketError::Io(inner) => inner,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::checksum::PROTOCOL_TCP;
+use crate::error::{EthParseError, ParseError};
+use crate::ip::Ipv4Header;
+use crate::tcp::Tcp;
+
+/// Length of an Ethernet II header: two MAC addresses and the EtherType.
+pub const ETH_HEADER_LEN: usize = 14;
+
+/// EtherType of an IPv4 payload.
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+
+/// Ethernet II frame header, as found in captures taken on a link-layer
+/// interface.
+///
+/// ```text
+/// +----------------+----------------+-----------+----------
+/// |  Destination   |     Source     | EtherType |  Payload
+/// |  MAC (6 bytes) |  MAC (6 bytes) | (2 bytes) |  ...
+/// +----------------+----------------+-----------+----------
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EthernetFrame {
+    pub dst_mac: [u8; 6],
+    pub src_mac: [u8; 6],
+    pub ethertype: u16,
+}
+
+impl TryFrom<&[u8]> for EthernetFrame {
+    type Error = EthParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < ETH_HEADER_LEN {
+            return Err(EthParseError::TooShort { got: bytes.len() });
+        }
+
+        Ok(Self {
+            dst_mac: bytes[0..6].try_into().unwrap(),
+            src_mac: bytes[6..12].try_into().unwrap(),
+            ethertype: u16::from_be_bytes(bytes[12..14].try_into().unwrap()),
+        })
+    }
+}
+
+/// Walks an Ethernet frame down to the TCP segment it carries, returning
+/// each layer's header and the TCP payload.
+///
+/// Bytes past the IPv4 total length, such as the padding that brings short
+/// frames up to 60 bytes, are not part of the payload.
+///
+/// # Errors
+/// - `NotIpv4` if the EtherType isn't 0x0800.
+/// - `NotTcp` if the IPv4 protocol isn't 6.
+/// - The layer's own error if any header fails to parse.
+pub fn parse_eth_ipv4_tcp(
+    bytes: &[u8],
+) -> Result<(EthernetFrame, Ipv4Header, Tcp, &[u8]), ParseError> {
+    let eth = EthernetFrame::try_from(bytes)?;
+    if eth.ethertype != ETHERTYPE_IPV4 {
+        return Err(ParseError::NotIpv4(eth.ethertype));
+    }
+
+    let (ip, _, segment) = Ipv4Header::parse_packet_raw(&bytes[ETH_HEADER_LEN..])?;
+    if ip.protocol != PROTOCOL_TCP {
+        return Err(ParseError::NotTcp(ip.protocol));
+    }
+
+    let (tcp, payload) = Tcp::parse_packet_raw(segment)?;
+
+    Ok((eth, ip, tcp, payload))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::{Ipv4ParseError, TcpParseError};
+    use crate::ip::build_ipv4_tcp;
+    use crate::tcp::TcpBuilder;
+    use alloc::vec::Vec;
+    use core::net::Ipv4Addr;
+
+    const DST_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+    const SRC_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+
+    fn frame(ethertype: u16, datagram: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&DST_MAC);
+        frame.extend_from_slice(&SRC_MAC);
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(datagram);
+        frame
+    }
+
+    fn datagram(payload: &[u8]) -> Vec<u8> {
+        let src_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let dst_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let tcp = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(80)
+            .build(src_ip, dst_ip, payload);
+        build_ipv4_tcp(Ipv4Header::new(src_ip, dst_ip, PROTOCOL_TCP), &tcp, payload)
+    }
+
+    #[test]
+    fn test_eth_parse_all_layers() {
+        // Short frames are padded to 60 bytes on the wire.
+        let mut bytes = frame(ETHERTYPE_IPV4, &datagram(b"hi"));
+        bytes.resize(60, 0);
+
+        let (eth, ip, tcp, payload) = parse_eth_ipv4_tcp(&bytes).unwrap();
+        assert_eq!(eth.dst_mac, DST_MAC);
+        assert_eq!(eth.src_mac, SRC_MAC);
+        assert_eq!(ip.dst, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(tcp.dest_port, 80);
+        assert_eq!(payload, b"hi");
+        assert!(tcp.verify_checksum(ip.src, ip.dst, payload));
+    }
+
+    #[test]
+    fn test_eth_parse_errors() {
+        assert_eq!(
+            EthernetFrame::try_from(&DST_MAC[..]).unwrap_err(),
+            EthParseError::TooShort { got: 6 }
+        );
+
+        // An IPv6 frame (0x86DD).
+        assert_eq!(
+            parse_eth_ipv4_tcp(&frame(0x86DD, &datagram(b""))).unwrap_err(),
+            ParseError::NotIpv4(0x86DD)
+        );
+
+        let mut udp = datagram(b"");
+        udp[9] = 17;
+        assert_eq!(
+            parse_eth_ipv4_tcp(&frame(ETHERTYPE_IPV4, &udp)).unwrap_err(),
+            ParseError::NotTcp(17)
+        );
+
+        assert_eq!(
+            parse_eth_ipv4_tcp(&frame(ETHERTYPE_IPV4, &[0x45; 10])).unwrap_err(),
+            ParseError::Ipv4(Ipv4ParseError::TooShort { got: 10 })
+        );
+
+        // The IPv4 header is fine but the TCP header is cut short.
+        let truncated = datagram(b"");
+        assert_eq!(
+            parse_eth_ipv4_tcp(&frame(ETHERTYPE_IPV4, &truncated[..30])).unwrap_err(),
+            ParseError::Tcp(TcpParseError::TooShort { got: 10 })
+        );
+    }
+}
@@ -1,9 +1,11 @@
+use crate::error::{FlagError, ParseFlagsError};
+use alloc::{vec, vec::Vec};
 use bitflags::bitflags;
-use std::fmt;
+use core::{fmt, str::FromStr};
 
 bitflags! {
     #[repr(transparent)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct TcpFlags: u8 {
         const UNINT = 0x00;
         const FIN = 0x01;
@@ -17,8 +19,54 @@ bitflags! {
     }
 }
 
-impl fmt::Display for TcpFlags {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// Common flag combinations.
+///
+/// These live outside the `bitflags!` block so they aren't treated as named
+/// flags of their own; `Display` keeps listing the individual bits.
+impl TcpFlags {
+    pub const SYN_ACK: Self = Self::SYN.union(Self::ACK);
+    pub const FIN_ACK: Self = Self::FIN.union(Self::ACK);
+    pub const PSH_ACK: Self = Self::PSH.union(Self::ACK);
+    pub const RST_ACK: Self = Self::RST.union(Self::ACK);
+}
+
+impl TcpFlags {
+    /// Checks for combinations no legitimate segment carries, such as SYN
+    /// with FIN or no flags at all, which mostly come from scanners probing
+    /// how a stack reacts.
+    ///
+    /// # Errors
+    /// The first offending combination found, checking SYN+FIN, SYN+RST,
+    /// RST+FIN, no flags and FIN without ACK in that order.
+    pub fn validate(&self) -> Result<(), FlagError> {
+        if self.contains(TcpFlags::SYN | TcpFlags::FIN) {
+            return Err(FlagError::SynFin);
+        }
+        if self.contains(TcpFlags::SYN | TcpFlags::RST) {
+            return Err(FlagError::SynRst);
+        }
+        if self.contains(TcpFlags::RST | TcpFlags::FIN) {
+            return Err(FlagError::RstFin);
+        }
+        if self.is_empty() {
+            return Err(FlagError::Null);
+        }
+        if self.contains(TcpFlags::FIN) && !self.contains(TcpFlags::ACK) {
+            return Err(FlagError::FinWithoutAck);
+        }
+        Ok(())
+    }
+
+    /// Whether `raw` has bits no flag is defined for. All 8 bits of the
+    /// flags byte are (NS sits apart, in the byte before), so this is
+    /// always false for now, but it follows `all()` should a bit ever be
+    /// left undefined, and parsers can check it instead of assuming.
+    pub fn has_unknown_bits(raw: u8) -> bool {
+        raw & !TcpFlags::all().bits() != 0
+    }
+
+    /// Names of the set flags, from FIN up to CWR.
+    pub(crate) fn names(&self) -> Vec<&'static str> {
         let mut flags = vec![];
 
         if self.contains(TcpFlags::FIN) {
@@ -46,6 +94,14 @@ impl fmt::Display for TcpFlags {
             flags.push("CWR");
         }
 
+        flags
+    }
+}
+
+impl fmt::Display for TcpFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.names();
+
         if flags.is_empty() {
             write!(f, "UNINT {}", self.bits())
         } else {
@@ -54,6 +110,86 @@ impl fmt::Display for TcpFlags {
     }
 }
 
+/// Parses flag names separated by `,` or `|`, in any case, e.g.
+/// `"SYN,ACK"` or `"syn | ack"`. This reads back `Display` output without
+/// its trailing number, `"UNINT"` being no flags.
+impl FromStr for TcpFlags {
+    type Err = ParseFlagsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = TcpFlags::empty();
+        for token in s.split([',', '|']) {
+            let name = token.trim().to_ascii_uppercase();
+            let flag = TcpFlags::from_name(&name).ok_or_else(|| ParseFlagsError {
+                token: token.trim().into(),
+            })?;
+            flags.insert(flag);
+        }
+        Ok(flags)
+    }
+}
+
+/// Serializes as the array of set flag names, e.g. `["SYN", "ACK"]`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TcpFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+/// Accepts either an array of flag names or the raw flags byte.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TcpFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use alloc::{format, string::String};
+
+        struct FlagsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for FlagsVisitor {
+            type Value = TcpFlags;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an array of TCP flag names or a flags byte")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, bits: u64) -> Result<TcpFlags, E> {
+                u8::try_from(bits)
+                    .ok()
+                    .and_then(TcpFlags::from_bits)
+                    .ok_or_else(|| E::custom(format!("invalid TCP flags byte: {}", bits)))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<TcpFlags, A::Error> {
+                let mut flags = TcpFlags::empty();
+                while let Some(name) = seq.next_element::<String>()? {
+                    let flag = TcpFlags::from_name(&name).ok_or_else(|| {
+                        serde::de::Error::custom(format!("unknown TCP flag: {}", name))
+                    })?;
+                    flags.insert(flag);
+                }
+                Ok(flags)
+            }
+        }
+
+        deserializer.deserialize_any(FlagsVisitor)
+    }
+}
+
+/// Any flags byte: each of the 8 bits is a flag.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for TcpFlags {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(TcpFlags::from_bits_retain(u.arbitrary()?))
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <u8 as arbitrary::Arbitrary>::size_hint(depth)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -73,6 +209,107 @@ mod tests {
         assert!(flags.contains(TcpFlags::FIN));
     }
 
+    #[test]
+    fn test_flags_combinations() {
+        assert_eq!(TcpFlags::SYN_ACK, TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(TcpFlags::FIN_ACK, TcpFlags::FIN | TcpFlags::ACK);
+        assert_eq!(TcpFlags::PSH_ACK, TcpFlags::PSH | TcpFlags::ACK);
+        assert_eq!(TcpFlags::RST_ACK, TcpFlags::RST | TcpFlags::ACK);
+    }
+
+    #[test]
+    fn test_flags_combination_display() {
+        assert_eq!(TcpFlags::SYN_ACK.to_string(), "SYN | ACK 18");
+    }
+
+    #[test]
+    fn test_flags_validate() {
+        let illegal = [
+            (TcpFlags::SYN | TcpFlags::FIN, FlagError::SynFin),
+            (TcpFlags::SYN | TcpFlags::RST, FlagError::SynRst),
+            (TcpFlags::RST | TcpFlags::FIN, FlagError::RstFin),
+            (TcpFlags::empty(), FlagError::Null),
+            (TcpFlags::FIN, FlagError::FinWithoutAck),
+            (
+                TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG,
+                FlagError::FinWithoutAck,
+            ),
+        ];
+        for (flags, error) in illegal {
+            assert_eq!(flags.validate(), Err(error), "{}", flags);
+            assert!(flags.contains(error.flags()));
+            // ACK doesn't make the pairs legal.
+            if error != FlagError::Null && error != FlagError::FinWithoutAck {
+                assert_eq!((flags | TcpFlags::ACK).validate(), Err(error));
+            }
+        }
+        assert_eq!(
+            (TcpFlags::SYN | TcpFlags::FIN | TcpFlags::RST).validate(),
+            Err(FlagError::SynFin)
+        );
+
+        for flags in [
+            TcpFlags::SYN,
+            TcpFlags::SYN_ACK,
+            TcpFlags::ACK,
+            TcpFlags::PSH_ACK,
+            TcpFlags::FIN_ACK,
+            TcpFlags::RST,
+            TcpFlags::RST_ACK,
+            TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR,
+        ] {
+            assert_eq!(flags.validate(), Ok(()), "{}", flags);
+        }
+    }
+
+    #[test]
+    fn test_flags_from_str() {
+        assert_eq!("SYN|ACK".parse(), Ok(TcpFlags::SYN_ACK));
+        assert_eq!("syn,ack".parse(), Ok(TcpFlags::SYN_ACK));
+        assert_eq!(
+            " Fin | psh ,URG ".parse(),
+            Ok(TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG)
+        );
+        assert_eq!(
+            "SYN,XYZ".parse::<TcpFlags>(),
+            Err(ParseFlagsError {
+                token: "XYZ".into()
+            })
+        );
+        assert!("".parse::<TcpFlags>().is_err());
+        assert!("SYN,,ACK".parse::<TcpFlags>().is_err());
+    }
+
+    #[test]
+    fn test_flags_from_str_reads_display() {
+        for bits in [0, 0x02, 0x12, 0x19, 0xFF] {
+            let flags = TcpFlags::from_bits_retain(bits);
+            let shown = flags.to_string();
+            let (names, _bits) = shown.rsplit_once(' ').unwrap();
+            assert_eq!(names.parse(), Ok(flags), "{}", shown);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_flags_serde_names() {
+        let json = serde_json::to_string(&TcpFlags::SYN_ACK).unwrap();
+        assert_eq!(json, r#"["SYN","ACK"]"#);
+
+        let flags: TcpFlags = serde_json::from_str(&json).unwrap();
+        assert_eq!(flags, TcpFlags::SYN_ACK);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_flags_serde_raw_byte() {
+        let flags: TcpFlags = serde_json::from_str("18").unwrap();
+        assert_eq!(flags, TcpFlags::SYN_ACK);
+
+        assert!(serde_json::from_str::<TcpFlags>("256").is_err());
+        assert!(serde_json::from_str::<TcpFlags>(r#"["XYZ"]"#).is_err());
+    }
+
     #[test]
     fn teset_flags_remove() {
         let mut flags = TcpFlags::SYN | TcpFlags::FIN;
@@ -0,0 +1,445 @@
+use crate::flags::TcpFlags;
+use crate::ip::Ipv4Header;
+use crate::raw::RawTcpSocket;
+use crate::retransmit::{RetransmitQueue, MAX_RTO};
+use crate::seq::{self, seq_leq, seq_lt};
+use crate::stream::{negotiate_mss, Stream};
+use crate::tcp::{Tcp, TcpBuilder};
+use std::{
+    hash::{BuildHasher, Hasher},
+    io,
+    net::Ipv4Addr,
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long to wait for the SYN-ACK before giving up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Our MSS, announced on the SYN.
+const OUR_MSS: u16 = 1460;
+
+/// Wait before the first zero-window probe, doubled up to `MAX_RTO` while
+/// the window stays shut.
+const ZERO_WINDOW_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An established connection, as negotiated by `connect`, exchanging a byte
+/// stream over its raw socket.
+#[derive(Debug)]
+pub struct Connection {
+    stream: Stream,
+    socket: RawTcpSocket,
+    /// Segments `write` sent that the peer hasn't acknowledged yet.
+    retransmit: RetransmitQueue,
+    ecn: bool,
+    /// Data that arrived while `write` waited on a zero window.
+    received: Vec<u8>,
+}
+
+impl Connection {
+    pub fn src(&self) -> (Ipv4Addr, u16) {
+        self.stream.src()
+    }
+
+    pub fn dst(&self) -> (Ipv4Addr, u16) {
+        self.stream.dst()
+    }
+
+    /// The next sequence number we will send (SND.NXT).
+    pub fn seq_num(&self) -> u32 {
+        self.stream.seq_num()
+    }
+
+    /// The next sequence number we expect from the peer (RCV.NXT), i.e.
+    /// what our segments acknowledge.
+    pub fn ack_num(&self) -> u32 {
+        self.stream.ack_num()
+    }
+
+    /// The smaller of our MSS and the one the peer announced.
+    pub fn mss(&self) -> u16 {
+        self.stream.mss()
+    }
+
+    /// Whether the peer accepted the ECN offered on our SYN. Nothing reacts
+    /// to ECE yet, but as we never send ECN-capable packets, routers have
+    /// nothing to mark.
+    pub fn ecn(&self) -> bool {
+        self.ecn
+    }
+
+    /// A keepalive probe for this connection, see `Stream::keepalive_probe`
+    /// and `keepalive::Keepalive` for when to send one.
+    pub fn keepalive_probe(&self) -> Tcp {
+        self.stream.keepalive_probe()
+    }
+
+    /// Sends `data`, cut into segments of at most one MSS, and blocks until
+    /// the peer has acknowledged all of it. A segment whose retransmission
+    /// timer fires is sent again, see `RetransmitQueue::resend_expired`.
+    ///
+    /// Fails with `TimedOut` once a segment has gone unacknowledged through
+    /// `MAX_RETRANSMITS` retransmissions, and with `ConnectionReset` if the
+    /// peer resets the connection.
+    ///
+    /// While the peer's window is zero, sends zero-window probes instead,
+    /// backing off up to `MAX_RTO`, until the window opens and the rest of
+    /// `data` can go out.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let (dst, _) = self.stream.dst();
+        let mut segments = self.stream.segments(data);
+        let mut interval = ZERO_WINDOW_PROBE_INTERVAL;
+
+        loop {
+            for segment in segments {
+                self.socket.send(&segment, dst)?;
+                let (seq, len) = match Tcp::parse_packet_raw(&segment) {
+                    Ok((tcp, payload)) => (tcp.seq_num, payload.len() as u32),
+                    Err(_) => continue,
+                };
+                self.retransmit.push(seq, len, segment, Instant::now());
+            }
+            if self.retransmit.timed_out(Instant::now()) {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            if let Some(packet) = self.retransmit.resend_expired(Instant::now()) {
+                self.socket.send(packet, dst)?;
+            }
+
+            let probe = self.stream.zero_window_probe();
+            let timeout = match (&probe, self.retransmit.next_expiry()) {
+                (Some(probe), _) => {
+                    self.socket.send(probe, dst)?;
+                    interval
+                }
+                // A zero read timeout would mean no timeout at all.
+                (None, Some(expiry)) => expiry
+                    .saturating_duration_since(Instant::now())
+                    .max(Duration::from_millis(1)),
+                (None, None) => return Ok(()),
+            };
+
+            self.socket.set_read_timeout(Some(timeout))?;
+            match self.receive_segment() {
+                Ok(data) => self.received.extend_from_slice(&data),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if probe.is_some() {
+                        interval = (interval * 2).min(MAX_RTO);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            segments = self.stream.flush();
+        }
+    }
+
+    /// Blocks until the peer's data continues the stream and returns it,
+    /// acknowledging it. Out-of-order segments are held until the gap
+    /// before them fills. An empty result means the peer closed its side, a
+    /// `ConnectionReset` error that it reset the connection.
+    pub fn read(&mut self) -> io::Result<Vec<u8>> {
+        if !self.received.is_empty() {
+            return Ok(std::mem::take(&mut self.received));
+        }
+        self.socket.set_read_timeout(None)?;
+
+        while !self.stream.fin_received() {
+            let data = self.receive_segment()?;
+            if !data.is_empty() {
+                return Ok(data);
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Takes in the next datagram, acknowledging new data, and returns the
+    /// bytes it makes available, if any. A RST from the peer fails with
+    /// `ConnectionReset`.
+    fn receive_segment(&mut self) -> io::Result<Vec<u8>> {
+        let (dst, _) = self.stream.dst();
+
+        let (datagram, _) = self.socket.recv()?;
+        let Ok(ip) = Ipv4Header::try_from(&datagram[..]) else {
+            return Ok(Vec::new());
+        };
+        if ip.src != dst {
+            return Ok(Vec::new());
+        }
+        let end = (ip.total_length as usize).clamp(ip.ihl as usize * 4, datagram.len());
+        let Ok((tcp, payload)) = Tcp::parse_packet_raw(&datagram[ip.ihl as usize * 4..end]) else {
+            return Ok(Vec::new());
+        };
+
+        if self.stream.is_reset(&tcp) {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+        let ours = (tcp.source_port, tcp.dest_port) == (self.dst().1, self.src().1);
+        if ours && tcp.flags.contains(TcpFlags::ACK) {
+            self.retransmit.on_ack(tcp.ack_num, Instant::now());
+        }
+        let ack_before = self.stream.ack_num();
+        let data = self.stream.receive(&tcp, payload);
+        if self.stream.ack_num() != ack_before {
+            self.socket
+                .send(&self.stream.ack().build_packet(b""), dst)?;
+        }
+        Ok(data)
+    }
+}
+
+/// Opens a connection with a three-way handshake over a raw socket: sends a
+/// SYN, waits for the matching SYN-ACK and replies with an ACK.
+///
+/// Requires root (or `CAP_NET_RAW`). As the kernel knows nothing about the
+/// connection, it will answer the SYN-ACK with a RST of its own unless
+/// outgoing RSTs from `src_port` are filtered, e.g. with:
+///
+/// ```text
+/// iptables -A OUTPUT -p tcp --tcp-flags RST RST --sport <src_port> -j DROP
+/// ```
+///
+/// Fails with `ConnectionRefused` if the peer answers with a RST and
+/// `TimedOut` if no answer arrives.
+pub async fn connect(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+) -> io::Result<Connection> {
+    tokio::task::spawn_blocking(move || connect_blocking(src, dst, src_port, dst_port))
+        .await
+        .map_err(io::Error::other)?
+}
+
+fn connect_blocking(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+) -> io::Result<Connection> {
+    let socket = RawTcpSocket::new()?;
+
+    let syn = TcpBuilder::new()
+        .source_port(src_port)
+        .dest_port(dst_port)
+        .seq_num(connection_seq(src, src_port, dst, dst_port))
+        .flags(TcpFlags::SYN)
+        .mss(OUR_MSS)
+        .ecn_setup()
+        .build(src, dst, b"");
+    socket.send(&syn.build_packet(b""), dst)?;
+
+    let syn_ack = wait_for_reply(&socket, &syn, dst, HANDSHAKE_TIMEOUT)?;
+    if syn_ack.flags.contains(TcpFlags::RST) {
+        return Err(io::ErrorKind::ConnectionRefused.into());
+    }
+
+    let seq_num = syn.seq_num.wrapping_add(1);
+    let ack_num = syn_ack.seq_num.wrapping_add(1);
+    let ack = TcpBuilder::new()
+        .source_port(src_port)
+        .dest_port(dst_port)
+        .seq_num(seq_num)
+        .ack_num(ack_num)
+        .flags(TcpFlags::ACK)
+        .build(src, dst, b"");
+    socket.send(&ack.build_packet(b""), dst)?;
+
+    let mss = negotiate_mss(OUR_MSS, &syn_ack.options);
+    let mut stream = Stream::new((src, src_port), (dst, dst_port), seq_num, ack_num, mss);
+    stream.set_send_window(syn_ack.window_size as u32);
+    Ok(Connection {
+        stream,
+        socket,
+        retransmit: RetransmitQueue::new(),
+        ecn: ecn_negotiated(&syn, &syn_ack),
+        received: Vec::new(),
+    })
+}
+
+/// Reads from `socket` until a segment from `peer` answers `syn` (see
+/// `answers`), failing with `TimedOut` if none arrives within `timeout`.
+pub fn wait_for_reply(
+    socket: &RawTcpSocket,
+    syn: &Tcp,
+    peer: Ipv4Addr,
+    timeout: Duration,
+) -> io::Result<Tcp> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let datagram = match socket.recv() {
+            Ok((datagram, _)) => datagram,
+            Err(e) => match io::Error::from(e) {
+                e if e.kind() == io::ErrorKind::WouldBlock => continue,
+                e if e.kind() == io::ErrorKind::TimedOut => continue,
+                e => return Err(e),
+            },
+        };
+
+        // Raw IPv4 sockets hand us the IP header too.
+        let Ok(ip) = Ipv4Header::try_from(&datagram[..]) else {
+            continue;
+        };
+        if ip.src != peer {
+            continue;
+        }
+        let Ok(reply) = Tcp::try_from(&datagram[ip.ihl as usize * 4..]) else {
+            continue;
+        };
+        if answers(syn, &reply) {
+            return Ok(reply);
+        }
+    }
+}
+
+/// Whether the handshake agreed on ECN: an ECN-setup SYN answered by an
+/// ECN-setup SYN-ACK (RFC 3168 section 6.1.1).
+pub fn ecn_negotiated(syn: &Tcp, syn_ack: &Tcp) -> bool {
+    syn.is_ecn_setup_syn() && syn_ack.is_ecn_setup_syn_ack()
+}
+
+/// Whether `reply` answers `syn`: it comes back between the same ports and
+/// acknowledges the SYN, whose sequence number the SYN itself consumes.
+///
+/// Both a SYN-ACK and a RST-ACK from a closed port answer a SYN.
+pub fn answers(syn: &Tcp, reply: &Tcp) -> bool {
+    // In SYN-SENT an ACK is acceptable if ISS < SEG.ACK =< SND.NXT, and only
+    // the SYN has been sent, so SND.NXT is ISS + 1.
+    let iss = syn.seq_num;
+    let snd_nxt = iss.wrapping_add(1);
+
+    reply.source_port == syn.dest_port
+        && reply.dest_port == syn.source_port
+        && reply.flags.contains(TcpFlags::ACK)
+        && reply.flags.intersects(TcpFlags::SYN | TcpFlags::RST)
+        && seq_lt(iss, reply.ack_num)
+        && seq_leq(reply.ack_num, snd_nxt)
+}
+
+/// The RFC 6528 ISN for a connection, see `seq::initial_seq`, keyed with a
+/// secret picked once per process. `connect` and SYN probes alike take
+/// theirs from here.
+pub fn connection_seq(src: Ipv4Addr, src_port: u16, dst: Ipv4Addr, dst_port: u16) -> u32 {
+    static KEY: OnceLock<[u8; 16]> = OnceLock::new();
+    let key = KEY.get_or_init(|| {
+        // Each `RandomState` is seeded randomly.
+        let mut key = [0u8; 16];
+        for half in key.chunks_exact_mut(8) {
+            let random = std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish();
+            half.copy_from_slice(&random.to_le_bytes());
+        }
+        key
+    });
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    seq::initial_seq(src, src_port, dst, dst_port, key, now)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn segment(source_port: u16, dest_port: u16, seq: u32, ack: u32, flags: TcpFlags) -> Tcp {
+        TcpBuilder::new()
+            .source_port(source_port)
+            .dest_port(dest_port)
+            .seq_num(seq)
+            .ack_num(ack)
+            .flags(flags)
+            .build(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, b"")
+    }
+
+    #[test]
+    fn test_answers_syn_ack() {
+        let syn = segment(40000, 80, 1000, 0, TcpFlags::SYN);
+
+        assert!(answers(
+            &syn,
+            &segment(80, 40000, 5000, 1001, TcpFlags::SYN_ACK)
+        ));
+        assert!(answers(
+            &syn,
+            &segment(80, 40000, 0, 1001, TcpFlags::RST_ACK)
+        ));
+    }
+
+    #[test]
+    fn test_answers_rejects_mismatch() {
+        let syn = segment(40000, 80, 1000, 0, TcpFlags::SYN);
+
+        // Wrong ack number, wrong ports, and a bare ACK.
+        assert!(!answers(
+            &syn,
+            &segment(80, 40000, 5000, 1000, TcpFlags::SYN_ACK)
+        ));
+        assert!(!answers(
+            &syn,
+            &segment(81, 40000, 5000, 1001, TcpFlags::SYN_ACK)
+        ));
+        assert!(!answers(
+            &syn,
+            &segment(80, 40001, 5000, 1001, TcpFlags::SYN_ACK)
+        ));
+        assert!(!answers(
+            &syn,
+            &segment(80, 40000, 5000, 1001, TcpFlags::ACK)
+        ));
+    }
+
+    #[test]
+    fn test_answers_wrapping_seq() {
+        let syn = segment(40000, 80, u32::MAX, 0, TcpFlags::SYN);
+        assert!(answers(
+            &syn,
+            &segment(80, 40000, 5000, 0, TcpFlags::SYN_ACK)
+        ));
+    }
+
+    #[test]
+    fn test_ecn_negotiated() {
+        let syn = segment(
+            40000,
+            80,
+            1000,
+            0,
+            TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR,
+        );
+        let ecn_syn_ack = TcpFlags::SYN_ACK | TcpFlags::ECE;
+
+        assert!(ecn_negotiated(
+            &syn,
+            &segment(80, 40000, 5000, 1001, ecn_syn_ack)
+        ));
+        // The peer doesn't do ECN, or reflected our flags back.
+        assert!(!ecn_negotiated(
+            &syn,
+            &segment(80, 40000, 5000, 1001, TcpFlags::SYN_ACK)
+        ));
+        assert!(!ecn_negotiated(
+            &syn,
+            &segment(80, 40000, 5000, 1001, ecn_syn_ack | TcpFlags::CWR)
+        ));
+        // We didn't offer it.
+        assert!(!ecn_negotiated(
+            &segment(40000, 80, 1000, 0, TcpFlags::SYN),
+            &segment(80, 40000, 5000, 1001, ecn_syn_ack)
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::error::ParseError;
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+/// Bytes per line, as in `xxd`.
+const WIDTH: usize = 16;
+
+/// Formats `bytes` the way `xxd` does: an offset, 16 bytes in groups of
+/// two, and their printable ASCII, one line each.
+///
+/// ```text
+/// 00000000: c0a8 1f90 1234 5678 8765 4321 5012 00ff  .....4Vx.eC!P...
+/// 00000010: f00d 0000                                ....
+/// ```
+pub fn hexdump(bytes: &[u8]) -> String {
+    hexdump_at(bytes, 0)
+}
+
+/// Like `hexdump`, numbering the lines from `offset`, for dumping part of
+/// a packet.
+pub fn hexdump_at(bytes: &[u8], offset: usize) -> String {
+    let mut out = String::new();
+
+    for (i, line) in bytes.chunks(WIDTH).enumerate() {
+        let _ = write!(out, "{:08x}:", offset + i * WIDTH);
+
+        let mut hex = String::new();
+        for (j, byte) in line.iter().enumerate() {
+            if j % 2 == 0 {
+                hex.push(' ');
+            }
+            let _ = write!(hex, "{:02x}", byte);
+        }
+        // 8 groups of " xxxx".
+        let _ = write!(out, "{:<40}  ", hex);
+
+        out.extend(line.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Decodes a hex string such as Wireshark's "Copy as Hex Stream", ignoring
+/// whitespace and colons between the digits.
+///
+/// # Errors
+/// - `BadHexDigit` for any other character.
+/// - `OddHexLength` if the digits don't pair up into bytes.
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseError> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .map(|c| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or(ParseError::BadHexDigit(c))
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    if digits.len() % 2 != 0 {
+        return Err(ParseError::OddHexLength(digits.len()));
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
+
+/// Encodes `bytes` as lowercase hex digits, two per byte, without
+/// separators.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+/// Encodes `bytes` as standard base64 (RFC 4648), padded.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_hexdump_tcp_header() {
+        let header = [
+            0xC0, 0xA8, 0x1F, 0x90, 0x12, 0x34, 0x56, 0x78, 0x87, 0x65, 0x43, 0x21, 0x50, 0x12,
+            0x00, 0xFF, 0xF0, 0x0D, 0x00, 0x00,
+        ];
+        assert_eq!(
+            hexdump(&header),
+            "00000000: c0a8 1f90 1234 5678 8765 4321 5012 00ff  .....4Vx.eC!P...\n\
+             00000010: f00d 0000                                ....\n"
+        );
+    }
+
+    #[test]
+    fn test_hexdump_at_offset() {
+        assert_eq!(
+            hexdump_at(b"GET /", 0x34),
+            "00000034: 4745 5420 2f                             GET /\n"
+        );
+        assert_eq!(hexdump(&[]), "");
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(
+            decode_hex("c0:a8 1F90\n12").unwrap(),
+            [0xC0, 0xA8, 0x1F, 0x90, 0x12]
+        );
+        assert_eq!(encode_hex(&[0xC0, 0xA8, 0x00]), "c0a800");
+        assert_eq!(decode_hex("abc").unwrap_err(), ParseError::OddHexLength(3));
+        assert_eq!(decode_hex("0g").unwrap_err(), ParseError::BadHexDigit('g'));
+    }
+
+    #[test]
+    fn test_encode_base64_rfc4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, encoded) in vectors {
+            assert_eq!(encode_base64(input.as_bytes()), encoded, "{:?}", input);
+        }
+        assert_eq!(encode_base64(&[0xFB, 0xFF]), "+/8=");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::checksum::{self, PROTOCOL_TCP};
+use crate::error::IcmpParseError;
+use crate::ip::Ipv4Header;
+
+/// Echo Reply, the answer to a ping.
+pub const ICMP_ECHO_REPLY: u8 = 0;
+
+/// Destination Unreachable. Code 3 (port unreachable) answers a UDP probe
+/// to a closed port; codes 1, 2, 3, 9, 10 and 13 from a router or firewall
+/// mark a TCP port as filtered.
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+
+/// Time Exceeded: a router dropped the datagram because its TTL ran out.
+/// Code 0 is the TTL expiring in transit, which is what traceroute counts
+/// on.
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+/// The fixed part of an ICMP message (RFC 792), as carried in an IPv4
+/// datagram with protocol 1.
+///
+/// ```text
+///    0                   1                   2                   3
+///    0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |     Type      |     Code      |          Checksum             |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |                     Depends on the type                       |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpHeader {
+    pub type_: u8,
+    pub code: u8,
+    pub checksum: u16,
+}
+
+impl IcmpHeader {
+    /// Whether this is an error message, which quotes the start of the
+    /// datagram that caused it after the 8-byte header.
+    pub fn is_error(&self) -> bool {
+        matches!(self.type_, ICMP_DEST_UNREACHABLE | ICMP_TIME_EXCEEDED)
+    }
+}
+
+/// The start of the datagram an ICMP error is about: its IP header and at
+/// least the first 8 bytes of what it carried (RFC 792; routers following
+/// RFC 1812 may quote more). For TCP those 8 bytes hold the ports and the
+/// sequence number, enough to tell which probe the error answers.
+#[derive(Debug)]
+pub struct OriginalDatagram<'a> {
+    pub ip: Ipv4Header,
+    /// What follows the quoted IP header, cut short by the quoting.
+    pub transport: &'a [u8],
+}
+
+impl OriginalDatagram<'_> {
+    /// The source and destination ports, for TCP or UDP.
+    pub fn ports(&self) -> Option<(u16, u16)> {
+        let ports = self.transport.get(0..4)?;
+        Some((
+            u16::from_be_bytes([ports[0], ports[1]]),
+            u16::from_be_bytes([ports[2], ports[3]]),
+        ))
+    }
+
+    /// The sequence number, if the original was TCP.
+    pub fn tcp_seq(&self) -> Option<u32> {
+        if self.ip.protocol != PROTOCOL_TCP {
+            return None;
+        }
+        Some(u32::from_be_bytes(
+            self.transport.get(4..8)?.try_into().unwrap(),
+        ))
+    }
+}
+
+/// Parses the header of an ICMP `message` (everything after the IP
+/// header) and, for an error, the datagram it quotes.
+///
+/// # Errors
+/// - `TooShort` if `message` is under 8 bytes.
+/// - `NoOriginal` if the type isn't an error that quotes a datagram.
+/// - `Original` if the quoted IP header doesn't parse.
+pub fn parse_error_message(
+    message: &[u8],
+) -> Result<(IcmpHeader, OriginalDatagram<'_>), IcmpParseError> {
+    let icmp = IcmpHeader::try_from(message)?;
+    if !icmp.is_error() {
+        return Err(IcmpParseError::NoOriginal(icmp.type_));
+    }
+
+    let (ip, _, transport) = Ipv4Header::parse_packet_raw(&message[8..])?;
+    Ok((icmp, OriginalDatagram { ip, transport }))
+}
+
+/// Whether the checksum of an ICMP `message`, which covers the header and
+/// everything after it, is right.
+pub fn verify_checksum(message: &[u8]) -> bool {
+    // Summing the message with a correct checksum field gives all ones.
+    checksum::ones_complement_sum(message) == 0xFFFF
+}
+
+impl TryFrom<&[u8]> for IcmpHeader {
+    type Error = IcmpParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 8 {
+            return Err(IcmpParseError::TooShort { got: bytes.len() });
+        }
+
+        Ok(Self {
+            type_: bytes[0],
+            code: bytes[1],
+            checksum: u16::from_be_bytes(bytes[2..4].try_into().unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::Ipv4ParseError;
+    use crate::flags::TcpFlags;
+    use crate::ip::build_ipv4_tcp;
+    use crate::tcp::TcpBuilder;
+    use alloc::vec::Vec;
+    use core::net::Ipv4Addr;
+
+    /// A Time Exceeded message quoting the IP header and first 8 bytes of a
+    /// SYN, as a router would send it.
+    fn time_exceeded() -> Vec<u8> {
+        let src = Ipv4Addr::new(10, 0, 0, 1);
+        let dst = Ipv4Addr::new(10, 0, 0, 2);
+        let syn = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(443)
+            .seq_num(7)
+            .flags(TcpFlags::SYN)
+            .build(src, dst, b"");
+        let datagram = build_ipv4_tcp(Ipv4Header::new(src, dst, PROTOCOL_TCP), &syn, b"");
+
+        let mut message = vec![ICMP_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(&datagram[..28]);
+        let checksum = !checksum::ones_complement_sum(&message);
+        message[2..4].copy_from_slice(&checksum.to_be_bytes());
+        message
+    }
+
+    #[test]
+    fn test_icmp_header_from_bytes() {
+        let header = IcmpHeader::try_from(&[11, 0, 0xF4, 0xFF, 0, 0, 0, 0][..]).unwrap();
+        assert_eq!(header.type_, ICMP_TIME_EXCEEDED);
+        assert_eq!(header.code, 0);
+        assert_eq!(header.checksum, 0xF4FF);
+
+        assert_eq!(
+            IcmpHeader::try_from(&[11, 0, 0xF4, 0xFF][..]),
+            Err(IcmpParseError::TooShort { got: 4 })
+        );
+    }
+
+    #[test]
+    fn test_icmp_parse_error_original() {
+        let message = time_exceeded();
+        assert!(verify_checksum(&message));
+
+        let (icmp, original) = parse_error_message(&message).unwrap();
+        assert_eq!(icmp.type_, ICMP_TIME_EXCEEDED);
+        assert_eq!(original.ip.dst, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(original.ports(), Some((49320, 443)));
+        assert_eq!(original.tcp_seq(), Some(7));
+
+        let mut corrupted = message.clone();
+        corrupted[8 + 20] ^= 0x01;
+        assert!(!verify_checksum(&corrupted));
+    }
+
+    #[test]
+    fn test_icmp_parse_error_rejects() {
+        let mut message = time_exceeded();
+        message[0] = ICMP_ECHO_REPLY;
+        assert_eq!(
+            parse_error_message(&message).unwrap_err(),
+            IcmpParseError::NoOriginal(ICMP_ECHO_REPLY)
+        );
+
+        message[0] = ICMP_DEST_UNREACHABLE;
+        message[8] = 0x40; // Quoted IHL of 0.
+        assert_eq!(
+            parse_error_message(&message).unwrap_err(),
+            IcmpParseError::Original(Ipv4ParseError::BadIhl(0))
+        );
+    }
+}
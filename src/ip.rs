@@ -0,0 +1,324 @@
+use crate::checksum::{self, PROTOCOL_TCP};
+use crate::error::Ipv4ParseError;
+use crate::tcp::Tcp;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+
+/// IPv4 header (RFC 791).
+///
+///    0                   1                   2                   3
+///    0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |Version|  IHL  |Type of Service|          Total Length         |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |         Identification        |Flags|      Fragment Offset    |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |  Time to Live |    Protocol   |         Header Checksum       |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |                       Source Address                          |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |                    Destination Address                        |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+#[derive(Debug)]
+pub struct Ipv4Header {
+    pub version: u8,
+    /// Header length in 32-bit words.
+    pub ihl: u8,
+    pub tos: u8,
+    pub total_length: u16,
+    pub identification: u16,
+    /// The 3 flag bits: reserved, DF, MF.
+    pub flags: u8,
+    /// Fragment offset in 8-byte units (13 bits).
+    pub fragment_offset: u16,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub checksum: u16,
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+}
+
+impl Ipv4Header {
+    /// A header from `src` to `dst` for an unfragmented datagram (DF set)
+    /// with a TTL of 64. The total length and checksum are left at 0 for
+    /// the caller, e.g. `build_ipv4_tcp`, to fill in.
+    pub fn new(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8) -> Self {
+        Self {
+            version: 4,
+            ihl: 5,
+            tos: 0,
+            total_length: 0,
+            identification: 0,
+            flags: 0b010,
+            fragment_offset: 0,
+            ttl: 64,
+            protocol,
+            checksum: 0,
+            src,
+            dst,
+        }
+    }
+
+    /// Header length in bytes, options included, i.e. where the payload
+    /// starts.
+    pub fn header_len(&self) -> usize {
+        self.ihl as usize * 4
+    }
+
+    /// Parses a datagram into its header, the options between the fixed 20
+    /// bytes and the IHL, and the payload. Anything past the total length
+    /// is link-layer padding and is left out of the payload.
+    ///
+    /// # Errors
+    /// As `try_from`: an IHL below 5 words or past the end of `bytes` is
+    /// `BadIhl`, so the slicing can't go out of bounds.
+    pub fn parse_packet_raw(bytes: &[u8]) -> Result<(Ipv4Header, &[u8], &[u8]), Ipv4ParseError> {
+        let ip = Ipv4Header::try_from(bytes)?;
+        let header_len = ip.header_len();
+        let end = (ip.total_length as usize).clamp(header_len, bytes.len());
+        Ok((ip, &bytes[20..header_len], &bytes[header_len..end]))
+    }
+
+    /// Serializes the fixed 20-byte header. Options are not kept, so the IHL
+    /// is always written as 5.
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0u8; 20];
+        bytes[0] = (self.version << 4) | 5;
+        bytes[1] = self.tos;
+        bytes[2..4].copy_from_slice(&self.total_length.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.identification.to_be_bytes());
+        let flags_fragment = ((self.flags as u16) << 13) | (self.fragment_offset & 0x1FFF);
+        bytes[6..8].copy_from_slice(&flags_fragment.to_be_bytes());
+        bytes[8] = self.ttl;
+        bytes[9] = self.protocol;
+        bytes[10..12].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.src.octets());
+        bytes[16..20].copy_from_slice(&self.dst.octets());
+        bytes
+    }
+
+    /// Sets the source address, patching the checksum for it (RFC 1624)
+    /// rather than summing the header again.
+    pub fn set_src(&mut self, src: Ipv4Addr) {
+        self.checksum = checksum::update_address(self.checksum, self.src, src);
+        self.src = src;
+    }
+
+    /// Sets the destination address, patching the checksum like `set_src`.
+    pub fn set_dst(&mut self, dst: Ipv4Addr) {
+        self.checksum = checksum::update_address(self.checksum, self.dst, dst);
+        self.dst = dst;
+    }
+
+    /// Returns the ones' complement of the ones' complement sum of the
+    /// header's 16-bit words, computed with the checksum field as zero.
+    ///
+    /// Unlike TCP, the IPv4 checksum covers the header only.
+    pub fn calculate_checksum(&self) -> u16 {
+        let mut bytes = self.to_bytes();
+        bytes[10..12].copy_from_slice(&[0, 0]);
+
+        !checksum::ones_complement_sum(&bytes)
+    }
+}
+
+/// Whether the checksum of a raw IPv4 header, options included, is right.
+/// `header` must be exactly the IHL's worth of bytes.
+///
+/// `Ipv4Header` doesn't keep options, so the check runs on the bytes.
+pub fn verify_header_checksum(header: &[u8]) -> bool {
+    // Summing the header with a correct checksum field gives all ones.
+    checksum::ones_complement_sum(header) == 0xFFFF
+}
+
+/// Builds a whole IPv4 datagram carrying `tcp` and `payload`, for raw
+/// sockets that take the IP header too (`IP_HDRINCL`).
+///
+/// The protocol, total length and header checksum of `ip` are filled in
+/// here. The TCP checksum is taken as is, so `tcp` must have been built
+/// against `ip.src` and `ip.dst`.
+pub fn build_ipv4_tcp(mut ip: Ipv4Header, tcp: &Tcp, payload: &[u8]) -> Vec<u8> {
+    let total_length = 20 + tcp.header_len() + payload.len();
+    debug_assert!(total_length <= u16::MAX as usize, "IPv4 datagram too long");
+
+    ip.ihl = 5;
+    ip.protocol = PROTOCOL_TCP;
+    ip.total_length = total_length as u16;
+    ip.checksum = ip.calculate_checksum();
+
+    let mut datagram = ip.to_bytes().to_vec();
+    datagram.extend_from_slice(&tcp.build_packet(payload));
+    datagram
+}
+
+impl TryFrom<&[u8]> for Ipv4Header {
+    type Error = Ipv4ParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 20 {
+            return Err(Ipv4ParseError::TooShort { got: bytes.len() });
+        }
+
+        let version = bytes[0] >> 4;
+        if version != 4 {
+            return Err(Ipv4ParseError::BadVersion(version));
+        }
+
+        let ihl = bytes[0] & 0x0F;
+        if ihl < 5 || ihl as usize * 4 > bytes.len() {
+            return Err(Ipv4ParseError::BadIhl(ihl));
+        }
+
+        let flags_fragment = u16::from_be_bytes(bytes[6..8].try_into().unwrap());
+        Ok(Self {
+            version,
+            ihl,
+            tos: bytes[1],
+            total_length: u16::from_be_bytes(bytes[2..4].try_into().unwrap()),
+            identification: u16::from_be_bytes(bytes[4..6].try_into().unwrap()),
+            flags: (flags_fragment >> 13) as u8,
+            fragment_offset: flags_fragment & 0x1FFF,
+            ttl: bytes[8],
+            protocol: bytes[9],
+            checksum: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            src: Ipv4Addr::from(<[u8; 4]>::try_from(&bytes[12..16]).unwrap()),
+            dst: Ipv4Addr::from(<[u8; 4]>::try_from(&bytes[16..20]).unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{flags::TcpFlags, tcp::TcpBuilder};
+
+    const RAW_BYTES: [u8; 20] = [
+        0x45, 0x00, 0x00, 0x73, // Version 4, IHL 5, TOS 0, Total Length 115
+        0x00, 0x00, 0x40, 0x00, // Identification 0, Flags DF, Fragment Offset 0
+        0x40, 0x11, 0xB8, 0x61, // TTL 64, Protocol UDP (17), Checksum 0xB861
+        0xC0, 0xA8, 0x00, 0x01, // Source 192.168.0.1
+        0xC0, 0xA8, 0x00, 0xC7, // Destination 192.168.0.199
+    ];
+
+    #[test]
+    fn test_ipv4_header_from_bytes() {
+        let header = Ipv4Header::try_from(&RAW_BYTES[..]).unwrap();
+
+        assert_eq!(header.version, 4);
+        assert_eq!(header.ihl, 5);
+        assert_eq!(header.total_length, 115);
+        assert_eq!(header.flags, 0b010);
+        assert_eq!(header.fragment_offset, 0);
+        assert_eq!(header.ttl, 64);
+        assert_eq!(header.protocol, 17);
+        assert_eq!(header.checksum, 0xB861);
+        assert_eq!(header.src, Ipv4Addr::new(192, 168, 0, 1));
+        assert_eq!(header.dst, Ipv4Addr::new(192, 168, 0, 199));
+        assert_eq!(header.to_bytes(), RAW_BYTES);
+    }
+
+    /// As for TCP, multi-byte fields are big-endian on any host.
+    #[test]
+    fn test_ipv4_wire_byte_order() {
+        let bytes: [u8; 20] = [
+            0x45, 0x00, 0x05, 0xDC, // Total Length 1500 (little-endian 56325)
+            0x12, 0x34, 0x20, 0x01, // Identification 4660, Flags MF, Fragment Offset 1
+            0x40, 0x06, 0x00, 0x01, // TTL 64, Protocol TCP, Checksum 1
+            0x0A, 0x00, 0x00, 0x01, // Source 10.0.0.1
+            0xC0, 0xA8, 0x01, 0x02, // Destination 192.168.1.2
+        ];
+        let header = Ipv4Header::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(header.total_length, 1500);
+        assert_eq!(header.identification, 4660);
+        assert_eq!(header.flags, 0b001);
+        assert_eq!(header.fragment_offset, 1);
+        assert_eq!(header.checksum, 1);
+        assert_eq!(header.src, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(header.dst, Ipv4Addr::new(192, 168, 1, 2));
+    }
+
+    #[test]
+    fn test_ipv4_checksum_calculation() {
+        let header = Ipv4Header::try_from(&RAW_BYTES[..]).unwrap();
+        assert_eq!(header.calculate_checksum(), 0xB861);
+    }
+
+    #[test]
+    fn test_ipv4_header_invalid() {
+        assert_eq!(
+            Ipv4Header::try_from(&RAW_BYTES[..19]).unwrap_err(),
+            Ipv4ParseError::TooShort { got: 19 }
+        );
+
+        let mut raw_bytes = RAW_BYTES;
+        raw_bytes[0] = 0x65;
+        assert_eq!(
+            Ipv4Header::try_from(&raw_bytes[..]).unwrap_err(),
+            Ipv4ParseError::BadVersion(6)
+        );
+
+        raw_bytes[0] = 0x46;
+        assert_eq!(
+            Ipv4Header::try_from(&raw_bytes[..]).unwrap_err(),
+            Ipv4ParseError::BadIhl(6)
+        );
+    }
+
+    #[test]
+    fn test_ipv4_parse_packet_raw_options() {
+        // IHL 6: one word of options (3 NOPs and an EOL), then 2 bytes of
+        // payload and 2 of padding past the total length.
+        let mut raw_bytes = RAW_BYTES.to_vec();
+        raw_bytes[0] = 0x46;
+        raw_bytes[2..4].copy_from_slice(&26u16.to_be_bytes());
+        raw_bytes.extend_from_slice(&[1, 1, 1, 0, b'h', b'i', 0, 0]);
+
+        let (ip, options, payload) = Ipv4Header::parse_packet_raw(&raw_bytes).unwrap();
+        assert_eq!(ip.header_len(), 24);
+        assert_eq!(options, [1, 1, 1, 0]);
+        assert_eq!(payload, b"hi");
+
+        // An IHL of 0 would put the payload before the header.
+        raw_bytes[0] = 0x40;
+        assert_eq!(
+            Ipv4Header::parse_packet_raw(&raw_bytes).unwrap_err(),
+            Ipv4ParseError::BadIhl(0)
+        );
+    }
+
+    #[test]
+    fn test_ipv4_verify_header_checksum() {
+        assert!(verify_header_checksum(&RAW_BYTES));
+
+        let mut raw_bytes = RAW_BYTES;
+        raw_bytes[8] = 0x3F; // TTL decremented, checksum not updated.
+        assert!(!verify_header_checksum(&raw_bytes));
+    }
+
+    #[test]
+    fn test_build_ipv4_tcp() {
+        let src = Ipv4Addr::new(10, 0, 0, 1);
+        let dst = Ipv4Addr::new(10, 0, 0, 2);
+        let tcp = TcpBuilder::new()
+            .source_port(40000)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .mss(1460)
+            .build(src, dst, b"hi");
+
+        let datagram = build_ipv4_tcp(Ipv4Header::new(src, dst, 0), &tcp, b"hi");
+        assert_eq!(datagram.len(), 20 + 24 + 2);
+
+        let ip = Ipv4Header::try_from(&datagram[..]).unwrap();
+        assert_eq!(ip.total_length as usize, datagram.len());
+        assert_eq!(ip.protocol, PROTOCOL_TCP);
+        assert_eq!(ip.checksum, ip.calculate_checksum());
+        assert!(verify_header_checksum(&datagram[..20]));
+
+        let (parsed, payload) = Tcp::parse_packet_raw(&datagram[20..]).unwrap();
+        assert_eq!(payload, b"hi");
+        assert!(parsed.verify_checksum(ip.src, ip.dst, payload));
+    }
+}
@@ -0,0 +1,147 @@
+//! Keepalive timing (RFC 1122 4.2.3.6), the way Linux does it: after `idle`
+//! without hearing from the peer, send a probe every `interval`, and give
+//! the peer up once `count` probes in a row go unanswered.
+//!
+//! The probes themselves come from `Stream::keepalive_probe`.
+
+use std::time::{Duration, Instant};
+
+/// When to probe and when to give up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Silence before the first probe.
+    pub idle: Duration,
+    /// Time between unanswered probes.
+    pub interval: Duration,
+    /// Unanswered probes before the peer is declared dead.
+    pub count: u32,
+}
+
+/// Linux's defaults: 2 hours idle, then 9 probes 75 seconds apart.
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(7200),
+            interval: Duration::from_secs(75),
+            count: 9,
+        }
+    }
+}
+
+/// What `Keepalive::poll` asks the caller to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveAction {
+    /// Nothing is due yet.
+    Wait,
+    /// Send a keepalive probe.
+    Probe,
+    /// The last probe went unanswered too: drop the connection.
+    Dead,
+}
+
+/// Decides when to send keepalive probes on an otherwise idle connection.
+#[derive(Debug)]
+pub struct Keepalive {
+    config: KeepaliveConfig,
+    /// When the peer was last heard from.
+    last_heard: Instant,
+    /// Probes sent since then.
+    probes_sent: u32,
+    last_probe: Option<Instant>,
+}
+
+impl Keepalive {
+    pub fn new(config: KeepaliveConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_heard: now,
+            probes_sent: 0,
+            last_probe: None,
+        }
+    }
+
+    /// Records a segment from the peer, which answers any probes so far.
+    pub fn on_segment(&mut self, now: Instant) {
+        self.last_heard = now;
+        self.probes_sent = 0;
+        self.last_probe = None;
+    }
+
+    /// Unanswered probes so far.
+    pub fn probes_sent(&self) -> u32 {
+        self.probes_sent
+    }
+
+    /// When `poll` will next ask for something.
+    pub fn deadline(&self) -> Instant {
+        match self.last_probe {
+            Some(probe) => probe + self.config.interval,
+            None => self.last_heard + self.config.idle,
+        }
+    }
+
+    /// What to do at `now`. `Probe` counts as sent.
+    pub fn poll(&mut self, now: Instant) -> KeepaliveAction {
+        if now < self.deadline() {
+            return KeepaliveAction::Wait;
+        }
+        if self.probes_sent >= self.config.count {
+            return KeepaliveAction::Dead;
+        }
+
+        self.probes_sent += 1;
+        self.last_probe = Some(now);
+        KeepaliveAction::Probe
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const CONFIG: KeepaliveConfig = KeepaliveConfig {
+        idle: Duration::from_secs(60),
+        interval: Duration::from_secs(10),
+        count: 3,
+    };
+
+    #[test]
+    fn test_probes_after_idle_then_dead() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(CONFIG, start);
+
+        assert_eq!(
+            keepalive.poll(start + Duration::from_secs(59)),
+            KeepaliveAction::Wait
+        );
+        let mut now = start + CONFIG.idle;
+        for probe in 1..=3 {
+            assert_eq!(keepalive.poll(now), KeepaliveAction::Probe);
+            assert_eq!(keepalive.probes_sent(), probe);
+            assert_eq!(keepalive.poll(now), KeepaliveAction::Wait);
+            now += CONFIG.interval;
+        }
+
+        assert_eq!(keepalive.deadline(), now);
+        assert_eq!(keepalive.poll(now), KeepaliveAction::Dead);
+    }
+
+    #[test]
+    fn test_answer_resets_probes() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(CONFIG, start);
+
+        let probe_at = start + CONFIG.idle;
+        assert_eq!(keepalive.poll(probe_at), KeepaliveAction::Probe);
+
+        let answer_at = probe_at + Duration::from_secs(1);
+        keepalive.on_segment(answer_at);
+        assert_eq!(keepalive.probes_sent(), 0);
+        assert_eq!(keepalive.deadline(), answer_at + CONFIG.idle);
+        assert_eq!(
+            keepalive.poll(probe_at + CONFIG.interval),
+            KeepaliveAction::Wait
+        );
+    }
+}
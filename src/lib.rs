@@ -1,2 +1,34 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod ack;
+pub mod bpf;
+pub mod checksum;
+pub mod congestion;
+pub mod error;
+pub mod eth;
 pub mod flags;
+#[cfg(feature = "std")]
+pub mod handshake;
+pub mod hexdump;
+pub mod icmp;
+pub mod ip;
+#[cfg(feature = "std")]
+pub mod keepalive;
+#[cfg(feature = "testing")]
+pub mod malformed;
+pub mod options;
+#[cfg(feature = "std")]
+pub mod pcap;
+#[cfg(feature = "std")]
+pub mod raw;
+#[cfg(feature = "std")]
+pub mod retransmit;
+pub mod segment;
+pub mod seq;
+pub mod state;
+pub mod stream;
 pub mod tcp;
+pub mod udp;
@@ -0,0 +1,193 @@
+use crate::checksum::{self, PseudoHeader, PROTOCOL_TCP};
+use crate::tcp::TcpBuilder;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+
+/// Builds segments that break the rules on purpose, for testing how other
+/// stacks cope: a wrong checksum, a data offset that doesn't match the
+/// header, reserved bits, or option bytes `TcpOption` can't express
+/// (overlapping, truncated, lengths that run past the header).
+///
+/// It starts from an ordinary `TcpBuilder` and patches the bytes it
+/// produces, skipping every check `TcpBuilder::build_checked` makes. Whatever
+/// isn't overridden comes out as the builder would build it, including a
+/// checksum that's right for the final bytes.
+///
+/// ```
+/// use harbinger::{malformed::RawTcpBuilder, tcp::Tcp};
+/// # use std::net::Ipv4Addr;
+/// let ip = Ipv4Addr::LOCALHOST;
+/// let packet = RawTcpBuilder::new(Tcp::builder().dest_port(80))
+///     .data_offset(4)
+///     .build_packet(ip, ip, b"");
+/// assert!(Tcp::try_from(&packet[..]).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RawTcpBuilder {
+    builder: TcpBuilder,
+    checksum: Option<u16>,
+    data_offset: Option<u8>,
+    reserved: Option<u8>,
+    options: Option<Vec<u8>>,
+}
+
+impl RawTcpBuilder {
+    pub fn new(builder: &TcpBuilder) -> Self {
+        Self {
+            builder: builder.clone(),
+            checksum: None,
+            data_offset: None,
+            reserved: None,
+            options: None,
+        }
+    }
+
+    /// Writes `checksum` as is instead of computing it.
+    pub fn checksum(&mut self, checksum: u16) -> &mut Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// Sets the data offset nibble to `words`, whatever the header's real
+    /// length. Panics if it doesn't fit in 4 bits.
+    pub fn data_offset(&mut self, words: u8) -> &mut Self {
+        assert!(words <= 0x0F, "the data offset is 4 bits");
+        self.data_offset = Some(words);
+        self
+    }
+
+    /// Sets the 3 reserved bits. Panics if `bits` doesn't fit in them.
+    pub fn reserved(&mut self, bits: u8) -> &mut Self {
+        assert!(bits <= 0x07, "there are 3 reserved bits");
+        self.reserved = Some(bits);
+        self
+    }
+
+    /// Replaces the builder's options with `bytes`, copied unparsed and
+    /// padded with zeros to a whole number of words.
+    pub fn raw_options(&mut self, bytes: &[u8]) -> &mut Self {
+        self.options = Some(bytes.to_vec());
+        self
+    }
+
+    /// The segment, header and `payload`, with the overrides applied. The
+    /// checksum is computed last, over the patched bytes, unless it was
+    /// overridden too.
+    pub fn build_packet(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> Vec<u8> {
+        let tcp = self.builder.build(src_ip, dst_ip, payload);
+        let mut bytes = tcp.build_packet(payload);
+
+        if let Some(options) = &self.options {
+            let mut header = bytes[..20].to_vec();
+            header.extend_from_slice(options);
+            header.resize(header.len().div_ceil(4) * 4, 0);
+            header[12] = ((header.len() / 4) as u8) << 4 | (header[12] & 0x0F);
+            header.extend_from_slice(payload);
+            bytes = header;
+        }
+        if let Some(words) = self.data_offset {
+            bytes[12] = words << 4 | (bytes[12] & 0x0F);
+        }
+        if let Some(bits) = self.reserved {
+            bytes[12] = (bytes[12] & 0xF1) | bits << 1;
+        }
+
+        let checksum = self.checksum.unwrap_or_else(|| {
+            bytes[16..18].fill(0);
+            let ph = PseudoHeader::V4 {
+                src: src_ip,
+                dst: dst_ip,
+            };
+            let sum = ph.sum(PROTOCOL_TCP, bytes.len() as u32)
+                + checksum::ones_complement_sum(&bytes) as u32;
+            !checksum::fold(sum)
+        });
+        bytes[16..18].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::TcpParseError;
+    use crate::flags::TcpFlags;
+    use crate::options::TcpOption;
+    use crate::tcp::Tcp;
+
+    const IP: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+    fn builder() -> TcpBuilder {
+        let mut builder = Tcp::builder();
+        builder
+            .source_port(49152)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .mss(1460);
+        builder
+    }
+
+    #[test]
+    fn test_raw_builder_without_overrides_matches_builder() {
+        let builder = builder();
+        assert_eq!(
+            RawTcpBuilder::new(&builder).build_packet(IP, IP, b"data"),
+            builder.build(IP, IP, b"data").build_packet(b"data")
+        );
+    }
+
+    #[test]
+    fn test_raw_builder_overrides() {
+        let packet = RawTcpBuilder::new(&builder())
+            .checksum(0xBEEF)
+            .build_packet(IP, IP, b"");
+        let tcp = Tcp::try_from(&packet[..]).unwrap();
+        assert_eq!(tcp.checksum, 0xBEEF);
+        assert!(!tcp.verify_checksum(IP, IP, b""));
+
+        let packet = RawTcpBuilder::new(&builder())
+            .reserved(0b101)
+            .build_packet(IP, IP, b"");
+        let tcp = Tcp::try_from(&packet[..]).unwrap();
+        assert_eq!(tcp.reserved, 0b101);
+        assert!(tcp.verify_checksum(IP, IP, b""));
+
+        // Claims 60 bytes of header in a 24-byte segment.
+        let packet = RawTcpBuilder::new(&builder())
+            .data_offset(15)
+            .build_packet(IP, IP, b"");
+        assert_eq!(packet[12] >> 4, 15);
+        assert_eq!(
+            Tcp::try_from(&packet[..]),
+            Err(TcpParseError::BadDataOffset(15))
+        );
+    }
+
+    #[test]
+    fn test_raw_builder_raw_options() {
+        // An MSS whose length swallows the NOP after it, then a window
+        // scale, unpadded.
+        let packet = RawTcpBuilder::new(&builder())
+            .raw_options(&[2, 5, 0x05, 0xB4, 1, 3, 3])
+            .build_packet(IP, IP, b"hi");
+        assert_eq!(packet.len(), 20 + 8 + 2);
+        assert_eq!(packet[12] >> 4, 7);
+        assert_eq!(&packet[20..28], [2, 5, 0x05, 0xB4, 1, 3, 3, 0]);
+        assert_eq!(&packet[28..], b"hi");
+
+        let (tcp, payload) = Tcp::parse_packet_raw(&packet).unwrap();
+        assert_eq!(payload, b"hi");
+        assert_eq!(
+            tcp.options,
+            [
+                TcpOption::Unknown {
+                    kind: 2,
+                    data: vec![0x05, 0xB4, 1]
+                },
+                TcpOption::WindowScale(0)
+            ]
+        );
+        assert!(tcp.verify_checksum(IP, IP, payload));
+    }
+}
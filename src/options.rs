@@ -0,0 +1,363 @@
+use crate::error::{BuildError, TcpParseError};
+use alloc::vec::Vec;
+
+/// A single entry in the TCP options region, which sits between the fixed
+/// 20-byte header and the payload.
+///
+/// Apart from EOL and NOP, every option is encoded as `kind`, `length`
+/// (covering the kind and length bytes themselves) and `length - 2` bytes of
+/// data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TcpOption {
+    /// End of option list (kind 0).
+    Eol,
+    /// No-operation (kind 1), used to align the following option.
+    Nop,
+    /// Maximum segment size (kind 2, length 4).
+    Mss(u16),
+    /// Window scale shift count (kind 3, length 3).
+    WindowScale(u8),
+    /// SACK permitted (kind 4, length 2), sent on SYN.
+    SackPermitted,
+    /// Selective acknowledgment blocks (kind 5, length 2 + 8 per block),
+    /// each the left and right edge of a received range.
+    Sack(Vec<(u32, u32)>),
+    /// Timestamps (kind 8, length 10): our clock value and the latest one
+    /// echoed back from the peer (RFC 7323).
+    Timestamp { tsval: u32, tsecr: u32 },
+    /// Any other kind, or a known one with a length it can't have, kept as
+    /// is so it serializes back byte for byte.
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+/// The options region can hold at most 40 bytes, which leaves room for 4
+/// SACK blocks.
+pub const MAX_SACK_BLOCKS: usize = 4;
+
+/// Bytes available for options: 15 words of header minus the fixed 5.
+pub const MAX_OPTIONS_LEN: usize = 40;
+
+impl TcpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            TcpOption::Eol => 0,
+            TcpOption::Nop => 1,
+            TcpOption::Mss(_) => 2,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 4,
+            TcpOption::Sack(_) => 5,
+            TcpOption::Timestamp { .. } => 8,
+            TcpOption::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// Number of bytes this option takes on the wire.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            TcpOption::Eol | TcpOption::Nop => 1,
+            TcpOption::Mss(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 2,
+            TcpOption::Sack(blocks) => 2 + 8 * blocks.len(),
+            TcpOption::Timestamp { .. } => 10,
+            TcpOption::Unknown { data, .. } => 2 + data.len(),
+        }
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            TcpOption::Eol | TcpOption::Nop => buf.push(self.kind()),
+            TcpOption::Mss(mss) => {
+                buf.extend_from_slice(&[self.kind(), 4]);
+                buf.extend_from_slice(&mss.to_be_bytes());
+            }
+            TcpOption::WindowScale(shift) => buf.extend_from_slice(&[self.kind(), 3, *shift]),
+            TcpOption::SackPermitted => buf.extend_from_slice(&[self.kind(), 2]),
+            TcpOption::Sack(blocks) => {
+                // More blocks could overflow the length byte; `check` catches
+                // them before serializing.
+                assert!(
+                    blocks.len() <= MAX_SACK_BLOCKS,
+                    "{} SACK blocks given, at most 4 fit",
+                    blocks.len()
+                );
+                buf.extend_from_slice(&[self.kind(), self.encoded_len() as u8]);
+                for (left, right) in blocks {
+                    buf.extend_from_slice(&left.to_be_bytes());
+                    buf.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+            TcpOption::Timestamp { tsval, tsecr } => {
+                buf.extend_from_slice(&[self.kind(), 10]);
+                buf.extend_from_slice(&tsval.to_be_bytes());
+                buf.extend_from_slice(&tsecr.to_be_bytes());
+            }
+            TcpOption::Unknown { kind, data } => {
+                buf.extend_from_slice(&[*kind, self.encoded_len() as u8]);
+                buf.extend_from_slice(data);
+            }
+        }
+    }
+}
+
+/// Bytes `options` take on the wire, padded to a 4-byte boundary.
+pub fn padded_len(options: &[TcpOption]) -> usize {
+    let len: usize = options.iter().map(TcpOption::encoded_len).sum();
+    len.div_ceil(4) * 4
+}
+
+/// Checks that `options` fit in a header: between 1 and `MAX_SACK_BLOCKS`
+/// SACK blocks in an option and no more than `MAX_OPTIONS_LEN` bytes in all,
+/// so the data offset can cover them. Returns their padded length.
+///
+/// # Errors
+/// `EmptySack`, `TooManySackBlocks` or `OptionsTooLong`.
+pub fn check(options: &[TcpOption]) -> Result<usize, BuildError> {
+    for option in options {
+        if let TcpOption::Sack(blocks) = option {
+            if blocks.is_empty() {
+                return Err(BuildError::EmptySack);
+            }
+            if blocks.len() > MAX_SACK_BLOCKS {
+                return Err(BuildError::TooManySackBlocks(blocks.len()));
+            }
+        }
+    }
+    let len = padded_len(options);
+    if len > MAX_OPTIONS_LEN {
+        return Err(BuildError::OptionsTooLong(len));
+    }
+    Ok(len)
+}
+
+/// Serializes `options` in order, padding the result with zeros (EOL) up to
+/// the next 4-byte boundary so it can be covered by the data offset.
+pub fn to_bytes(options: &[TcpOption]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for option in options {
+        option.write_to(&mut bytes);
+    }
+
+    let padded_len = bytes.len().div_ceil(4) * 4;
+    bytes.resize(padded_len, 0);
+    bytes
+}
+
+/// Decodes the options region of a TCP header (the bytes between offset 20
+/// and `data_offset * 4`).
+///
+/// Parsing stops at the first EOL, everything after it is padding. Kinds
+/// this parser doesn't know are kept as `TcpOption::Unknown`.
+///
+/// # Errors
+/// `BadOption` with the kind of a multi-byte option whose length byte is
+/// missing, below 2 or runs past the region. Every option takes at least
+/// one byte, so parsing always ends.
+pub fn parse(bytes: &[u8]) -> Result<Vec<TcpOption>, TcpParseError> {
+    let mut options = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            0 => break,
+            1 => {
+                options.push(TcpOption::Nop);
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let kind = bytes[i];
+        let len = match bytes.get(i + 1) {
+            Some(&len) if len >= 2 && i + len as usize <= bytes.len() => len as usize,
+            _ => return Err(TcpParseError::BadOption(kind)),
+        };
+
+        let data = &bytes[i + 2..i + len];
+        match (kind, data.len()) {
+            (2, 2) => options.push(TcpOption::Mss(u16::from_be_bytes([data[0], data[1]]))),
+            (3, 1) => options.push(TcpOption::WindowScale(data[0])),
+            (4, 0) => options.push(TcpOption::SackPermitted),
+            (5, n) if n > 0 && n % 8 == 0 => options.push(TcpOption::Sack(
+                data.chunks_exact(8)
+                    .map(|block| {
+                        (
+                            u32::from_be_bytes(block[0..4].try_into().unwrap()),
+                            u32::from_be_bytes(block[4..8].try_into().unwrap()),
+                        )
+                    })
+                    .collect(),
+            )),
+            (8, 8) => options.push(TcpOption::Timestamp {
+                tsval: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+                tsecr: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            }),
+            (kind, _) => options.push(TcpOption::Unknown {
+                kind,
+                data: data.to_vec(),
+            }),
+        }
+        i += len;
+    }
+
+    Ok(options)
+}
+
+/// Options that serialize to what `parse` reads back: no EOL, which ends
+/// the list, between 1 and `MAX_SACK_BLOCKS` SACK blocks, and `Unknown`
+/// only with kinds past 8 (so they can't be mistaken for known ones) and at
+/// most 38 bytes of data.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for TcpOption {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=6)? {
+            0 => TcpOption::Nop,
+            1 => TcpOption::Mss(u.arbitrary()?),
+            2 => TcpOption::WindowScale(u.arbitrary()?),
+            3 => TcpOption::SackPermitted,
+            4 => {
+                let count = u.int_in_range(1..=MAX_SACK_BLOCKS)?;
+                TcpOption::Sack(
+                    (0..count)
+                        .map(|_| u.arbitrary())
+                        .collect::<arbitrary::Result<_>>()?,
+                )
+            }
+            5 => TcpOption::Timestamp {
+                tsval: u.arbitrary()?,
+                tsecr: u.arbitrary()?,
+            },
+            _ => {
+                let kind = u.int_in_range(9..=u8::MAX)?;
+                let len = u.int_in_range(0..=MAX_OPTIONS_LEN - 2)?;
+                TcpOption::Unknown {
+                    kind,
+                    data: u.bytes(len)?.to_vec(),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_options_to_bytes_pads_to_word() {
+        let bytes = to_bytes(&[TcpOption::Mss(1460), TcpOption::WindowScale(7)]);
+        assert_eq!(bytes, [2, 4, 0x05, 0xB4, 3, 3, 7, 0]);
+    }
+
+    #[test]
+    fn test_options_parse_known_kinds() {
+        let bytes = [2, 4, 0x05, 0xB4, 1, 3, 3, 7];
+        assert_eq!(
+            parse(&bytes).unwrap(),
+            [
+                TcpOption::Mss(1460),
+                TcpOption::Nop,
+                TcpOption::WindowScale(7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options_parse_stops_at_eol() {
+        let bytes = [3, 3, 7, 0, 2, 4, 0x05, 0xB4];
+        assert_eq!(parse(&bytes).unwrap(), [TcpOption::WindowScale(7)]);
+    }
+
+    #[test]
+    fn test_options_parse_bad_length_does_not_loop() {
+        // MSS with a length of 0, which would never move on.
+        assert_eq!(parse(&[2, 0, 0, 0]), Err(TcpParseError::BadOption(2)));
+        assert_eq!(parse(&[1, 2, 9, 0]), Err(TcpParseError::BadOption(2)));
+        assert_eq!(parse(&[1, 1, 1, 8]), Err(TcpParseError::BadOption(8)));
+        assert_eq!(parse(&[1, 1, 1, 1]), Ok(vec![TcpOption::Nop; 4]));
+    }
+
+    #[test]
+    fn test_options_check_limits() {
+        let blocks = vec![(1, 2); 5];
+        assert_eq!(check(&[TcpOption::Sack(blocks[..4].to_vec())]), Ok(36));
+        assert_eq!(
+            check(&[TcpOption::Sack(blocks)]),
+            Err(BuildError::TooManySackBlocks(5))
+        );
+        // It would go out as [5, 2] and come back as an unknown option.
+        assert_eq!(
+            check(&[TcpOption::Sack(vec![])]),
+            Err(BuildError::EmptySack)
+        );
+
+        // MSS, window scale and timestamps with 4 SACK blocks: 4 + 3 + 10 +
+        // 34 = 51 bytes.
+        let options = [
+            TcpOption::Mss(1460),
+            TcpOption::WindowScale(7),
+            TcpOption::Timestamp { tsval: 1, tsecr: 2 },
+            TcpOption::Sack(vec![(1, 2); 4]),
+        ];
+        assert_eq!(check(&options), Err(BuildError::OptionsTooLong(52)));
+        assert_eq!(padded_len(&options), 52);
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 4 fit")]
+    fn test_options_to_bytes_rejects_five_sack_blocks() {
+        to_bytes(&[TcpOption::Sack(vec![(1, 2); 5])]);
+    }
+
+    #[test]
+    fn test_options_sack_round_trip() {
+        let options = [
+            TcpOption::SackPermitted,
+            TcpOption::Sack(vec![(1000, 2000), (0xFFFF_FF00, 0x10)]),
+        ];
+        let bytes = to_bytes(&options);
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(&bytes[..4], [4, 2, 5, 18]);
+        assert_eq!(parse(&bytes).unwrap(), options);
+    }
+
+    #[test]
+    fn test_options_timestamp_round_trip() {
+        let options = [
+            TcpOption::Nop,
+            TcpOption::Nop,
+            TcpOption::Timestamp {
+                tsval: 0x01020304,
+                tsecr: 7,
+            },
+        ];
+        let bytes = to_bytes(&options);
+        assert_eq!(bytes, [1, 1, 8, 10, 1, 2, 3, 4, 0, 0, 0, 7]);
+        assert_eq!(parse(&bytes).unwrap(), options);
+    }
+
+    #[test]
+    fn test_options_unknown_kept() {
+        // A made-up kind 42 with 3 bytes of data, and an MSS of the wrong
+        // length.
+        let bytes = [42, 5, 0xAA, 0xBB, 0xCC, 2, 3, 0x05];
+        let options = parse(&bytes).unwrap();
+        assert_eq!(
+            options,
+            [
+                TcpOption::Unknown {
+                    kind: 42,
+                    data: vec![0xAA, 0xBB, 0xCC]
+                },
+                TcpOption::Unknown {
+                    kind: 2,
+                    data: vec![0x05]
+                },
+            ]
+        );
+        assert_eq!(to_bytes(&options), bytes);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Magic number of the classic pcap format with microsecond timestamps.
+pub const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+/// `LINKTYPE_RAW`: packets start at the IP header, as a raw IPv4 socket
+/// hands them to us.
+pub const LINKTYPE_RAW: u32 = 101;
+/// `LINKTYPE_ETHERNET`: packets start at an Ethernet II header.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+const SNAPLEN: u32 = 65535;
+
+/// Writes packets in the classic libpcap format that Wireshark and tcpdump
+/// read. All header fields are written little-endian; readers tell the byte
+/// order from the magic number.
+pub struct PcapWriter<W: Write = BufWriter<File>> {
+    writer: W,
+}
+
+impl PcapWriter {
+    /// Creates (or truncates) the file at `path` and writes the global header.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PcapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the global header to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // Major version.
+        writer.write_all(&4u16.to_le_bytes())?; // Minor version.
+        writer.write_all(&0i32.to_le_bytes())?; // GMT to local correction.
+        writer.write_all(&0u32.to_le_bytes())?; // Accuracy of timestamps.
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+        Ok(Self { writer })
+    }
+
+    /// Appends one record: the per-packet header followed by `bytes`.
+    pub fn write_packet(&mut self, bytes: &[u8], ts: SystemTime) -> io::Result<()> {
+        let ts = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = bytes.len() as u32;
+
+        self.writer
+            .write_all(&(ts.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&ts.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?; // Captured length.
+        self.writer.write_all(&len.to_le_bytes())?; // Original length.
+        self.writer.write_all(bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads packets back from a classic pcap file, in either byte order.
+///
+/// Iterating yields each record's packet bytes. A record cut short by the
+/// end of the input yields an `UnexpectedEof` error, after which iteration
+/// stops.
+pub struct PcapReader<R: Read = BufReader<File>> {
+    reader: R,
+    big_endian: bool,
+    linktype: u32,
+    done: bool,
+}
+
+impl PcapReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PcapReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads and validates the global header from `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                io::Error::new(e.kind(), "truncated pcap global header")
+            } else {
+                e
+            }
+        })?;
+
+        let magic: [u8; 4] = header[0..4].try_into().unwrap();
+        let big_endian = if magic == PCAP_MAGIC.to_le_bytes() {
+            false
+        } else if magic == PCAP_MAGIC.to_be_bytes() {
+            true
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a pcap file, magic: {:02x?}", magic),
+            ));
+        };
+
+        let mut pcap = Self {
+            reader,
+            big_endian,
+            linktype: 0,
+            done: false,
+        };
+        pcap.linktype = pcap.u32_at(&header, 20);
+        Ok(pcap)
+    }
+
+    /// The link-layer type every packet in the file starts with.
+    pub fn linktype(&self) -> u32 {
+        self.linktype
+    }
+
+    fn u32_at(&self, bytes: &[u8], at: usize) -> u32 {
+        let word = bytes[at..at + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(word)
+        } else {
+            u32::from_le_bytes(word)
+        }
+    }
+
+    /// Fills `buf`, returning `false` if the input ended before any byte.
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "truncated pcap record header",
+                    ))
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; 16];
+        if !self.read_or_eof(&mut header)? {
+            return Ok(None);
+        }
+
+        let captured_len = self.u32_at(&header, 8) as usize;
+        if captured_len > SNAPLEN as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("pcap record too large: {} bytes", captured_len),
+            ));
+        }
+
+        let mut packet = vec![0u8; captured_len];
+        self.reader.read_exact(&mut packet).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                io::Error::new(
+                    e.kind(),
+                    format!("truncated pcap record, expected {} bytes", captured_len),
+                )
+            } else {
+                e
+            }
+        })?;
+        Ok(Some(packet))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.next_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{process::Command, time::Duration};
+
+    const PACKET: [u8; 40] = [
+        0x45, 0x00, 0x00, 0x28, 0x00, 0x01, 0x40, 0x00, 0x40, 0x06, 0x3C, 0xCD, 0x7F, 0x00, 0x00,
+        0x01, 0x7F, 0x00, 0x00, 0x01, // IPv4: 127.0.0.1 > 127.0.0.1, TCP.
+        0x9C, 0x40, 0x00, 0x50, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x50, 0x02, 0x04,
+        0x00, 0x00, 0x00, 0x00, 0x00, // TCP: 40000 > 80 [SYN].
+    ];
+
+    #[test]
+    fn test_pcap_byte_layout() {
+        let ts = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_000);
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        writer.write_packet(&PACKET, ts).unwrap();
+        let bytes = writer.into_inner();
+
+        assert_eq!(bytes.len(), 24 + 16 + PACKET.len());
+        assert_eq!(
+            bytes[..24],
+            [
+                0xD4, 0xC3, 0xB2, 0xA1, // Magic.
+                0x02, 0x00, 0x04, 0x00, // Version 2.4.
+                0x00, 0x00, 0x00, 0x00, // Timezone.
+                0x00, 0x00, 0x00, 0x00, // Sigfigs.
+                0xFF, 0xFF, 0x00, 0x00, // Snaplen.
+                0x65, 0x00, 0x00, 0x00, // Linktype RAW (101).
+            ]
+        );
+        assert_eq!(bytes[24..28], 1_700_000_000u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 123_456u32.to_le_bytes());
+        assert_eq!(bytes[32..36], 40u32.to_le_bytes());
+        assert_eq!(bytes[36..40], 40u32.to_le_bytes());
+        assert_eq!(bytes[40..], PACKET);
+    }
+
+    fn capture(packets: &[&[u8]]) -> Vec<u8> {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for packet in packets {
+            writer.write_packet(packet, SystemTime::now()).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_pcap_reader_round_trip() {
+        let bytes = capture(&[&PACKET, &PACKET[..20]]);
+        let reader = PcapReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.linktype(), LINKTYPE_RAW);
+
+        let packets: Vec<Vec<u8>> = reader.map(Result::unwrap).collect();
+        assert_eq!(packets, [&PACKET[..], &PACKET[..20]]);
+    }
+
+    #[test]
+    fn test_pcap_reader_big_endian() {
+        let mut bytes = vec![
+            0xA1, 0xB2, 0xC3, 0xD4, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x65,
+        ];
+        bytes.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 3]);
+        bytes.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+
+        let mut reader = PcapReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.linktype(), LINKTYPE_RAW);
+        assert_eq!(reader.next().unwrap().unwrap(), [0xAA, 0xBB, 0xCC]);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_pcap_reader_bad_magic() {
+        let mut bytes = capture(&[]);
+        bytes[0] = 0x00;
+
+        let err = PcapReader::new(&bytes[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_pcap_reader_truncated_record() {
+        let bytes = capture(&[&PACKET]);
+
+        // Cut inside the packet data, then inside the record header.
+        for len in [bytes.len() - 1, 24 + 10] {
+            let mut reader = PcapReader::new(&bytes[..len]).unwrap();
+            let err = reader.next().unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_pcap_opens_in_tshark() {
+        // Only meaningful where Wireshark is installed.
+        if Command::new("tshark").arg("--version").output().is_err() {
+            return;
+        }
+
+        let path = std::env::temp_dir().join(format!("harbinger-{}.pcap", std::process::id()));
+        let mut writer = PcapWriter::create(&path).unwrap();
+        writer.write_packet(&PACKET, SystemTime::now()).unwrap();
+        writer.flush().unwrap();
+
+        let output = Command::new("tshark")
+            .arg("-r")
+            .arg(&path)
+            .output()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("40000"));
+    }
+}
@@ -0,0 +1,406 @@
+#[cfg(target_os = "linux")]
+use crate::bpf::BpfInstruction;
+use crate::error::RawSocketError;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, RawFd};
+use std::{
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+#[cfg(unix)]
+use tokio::io::unix::AsyncFd;
+
+/// Largest IPv4 datagram, so a read never truncates.
+const MAX_DATAGRAM: usize = 65535;
+
+/// A raw IPv4 socket for TCP segments, hiding the platform differences.
+///
+/// Sockets from `new` send bare segments and the kernel adds the IP header;
+/// those from `with_ip_header` send whole datagrams as built by
+/// `ip::build_ipv4_tcp`. Received datagrams include the IP header either
+/// way, on every platform.
+///
+/// On Linux the kernel stamps each received datagram with its arrival time
+/// (`SO_TIMESTAMPNS`), see `recv_with_timestamp`.
+///
+/// Needs root or `CAP_NET_RAW`; without them `new` fails with
+/// `RawSocketError::PermissionDenied`. Note that macOS (like the other BSDs)
+/// never delivers TCP to raw sockets, so there `recv` only sees what the
+/// socket was explicitly sent.
+#[derive(Debug)]
+pub struct RawTcpSocket {
+    socket: Socket,
+    header_included: bool,
+}
+
+impl RawTcpSocket {
+    pub fn new() -> Result<Self, RawSocketError> {
+        let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP))?;
+
+        // With IP_HDRINCL, macOS expects ip_len and ip_off in host byte
+        // order rather than network order. Leave it off so the kernel writes
+        // the IP header there, as Linux does by default.
+        #[cfg(target_os = "macos")]
+        socket.set_header_included_v4(false)?;
+        #[cfg(target_os = "linux")]
+        enable_timestamps(&socket)?;
+
+        Ok(Self {
+            socket,
+            header_included: false,
+        })
+    }
+
+    /// A socket that sends whole IPv4 datagrams (`IP_HDRINCL`), letting the
+    /// caller pick every IP header field. `send` takes care of the macOS
+    /// byte order quirk, so the same datagram works on both platforms.
+    pub fn with_ip_header() -> Result<Self, RawSocketError> {
+        let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP))?;
+        socket.set_header_included_v4(true)?;
+        #[cfg(target_os = "linux")]
+        enable_timestamps(&socket)?;
+
+        Ok(Self {
+            socket,
+            header_included: true,
+        })
+    }
+
+    /// Only receive datagrams addressed to `addr`.
+    pub fn bind(&self, addr: Ipv4Addr) -> Result<(), RawSocketError> {
+        let addr = SockAddr::from(SocketAddr::new(addr.into(), 0));
+        Ok(self.socket.bind(&addr)?)
+    }
+
+    /// Makes `recv` give up with `WouldBlock` or `TimedOut` after `timeout`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), RawSocketError> {
+        Ok(self.socket.set_read_timeout(timeout)?)
+    }
+
+    /// Has the kernel run `program` (see `bpf::compile`) over every datagram
+    /// before queueing it, dropping those it rejects, so a busy host's
+    /// traffic never reaches `recv`. Replaces any filter attached before.
+    ///
+    /// Linux only (`SO_ATTACH_FILTER`); the BSDs filter with `/dev/bpf`
+    /// instead, which raw sockets can't use.
+    #[cfg(target_os = "linux")]
+    pub fn attach_filter(&self, program: &[BpfInstruction]) -> Result<(), RawSocketError> {
+        let program: Vec<_> = program
+            .iter()
+            .map(|insn| libc::sock_filter {
+                code: insn.code,
+                jt: insn.jt,
+                jf: insn.jf,
+                k: insn.k,
+            })
+            .collect();
+        Ok(self.socket.attach_filter(&program)?)
+    }
+
+    /// Sends a TCP segment (header, options and payload) to `dst`, or a
+    /// whole IPv4 datagram on a socket from `with_ip_header`.
+    pub fn send(&self, packet: &[u8], dst: Ipv4Addr) -> Result<usize, RawSocketError> {
+        let dst = SockAddr::from(SocketAddr::new(dst.into(), 0));
+
+        if cfg!(target_os = "macos") && self.header_included {
+            return Ok(self.socket.send_to(&bsd_hdrincl_order(packet), &dst)?);
+        }
+        Ok(self.socket.send_to(packet, &dst)?)
+    }
+
+    /// Blocks until a datagram arrives, returning it, IP header included,
+    /// along with the sender's address (port 0, raw sockets have none).
+    pub fn recv(&self) -> Result<(Vec<u8>, SocketAddr), RawSocketError> {
+        recv_datagram(&self.socket)
+    }
+
+    /// Like `recv`, also returning when the datagram arrived, as time since
+    /// the Unix epoch. On Linux that's the kernel's timestamp, taken before
+    /// the datagram waited in the socket's buffer, so it's fit for RTT
+    /// measurements. Elsewhere it's the time the read returned.
+    pub fn recv_with_timestamp(&self) -> Result<(Vec<u8>, SocketAddr, Duration), RawSocketError> {
+        let mut buffer = vec![0u8; MAX_DATAGRAM];
+        let (bytes_read, timestamp) = recv_timestamped(&self.socket, &mut buffer)?;
+        buffer.truncate(bytes_read);
+
+        let src = datagram_source(&buffer)?;
+        let timestamp = timestamp.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+        });
+        Ok((buffer, src, timestamp))
+    }
+}
+
+/// A raw IPv4 socket receiving ICMP, such as the Time Exceeded messages
+/// routers send back when a probe's TTL runs out. Needs the same
+/// privileges as `RawTcpSocket`.
+#[derive(Debug)]
+pub struct RawIcmpSocket {
+    socket: Socket,
+}
+
+impl RawIcmpSocket {
+    pub fn new() -> Result<Self, RawSocketError> {
+        let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
+        Ok(Self { socket })
+    }
+
+    /// Makes `recv` give up with `WouldBlock` or `TimedOut` after `timeout`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), RawSocketError> {
+        Ok(self.socket.set_read_timeout(timeout)?)
+    }
+
+    /// Blocks until an ICMP message arrives, returning it with its IP
+    /// header, along with the sender's address.
+    pub fn recv(&self) -> Result<(Vec<u8>, SocketAddr), RawSocketError> {
+        recv_datagram(&self.socket)
+    }
+}
+
+fn recv_datagram(socket: &Socket) -> Result<(Vec<u8>, SocketAddr), RawSocketError> {
+    // `Read` on a socket2 socket takes an initialized buffer and returns
+    // how much of it the kernel filled, so no uninitialized byte can be
+    // observed. The sender is taken from the IP header rather than from
+    // recvfrom's address, which socket2 only offers on uninit buffers.
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+    let bytes_read = (&*socket).read(&mut buffer)?;
+    if bytes_read > buffer.len() {
+        return Err(io::Error::other("raw socket read past the buffer").into());
+    }
+    buffer.truncate(bytes_read);
+
+    let src = datagram_source(&buffer)?;
+    Ok((buffer, src))
+}
+
+/// The sender of a received datagram, from its IP header.
+fn datagram_source(datagram: &[u8]) -> io::Result<SocketAddr> {
+    let src = datagram
+        .get(12..16)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "datagram too short"))?;
+    let src = Ipv4Addr::new(src[0], src[1], src[2], src[3]);
+    Ok(SocketAddr::new(src.into(), 0))
+}
+
+/// Asks the kernel to attach a `SCM_TIMESTAMPNS` control message with the
+/// arrival time to every datagram.
+#[cfg(target_os = "linux")]
+fn enable_timestamps(socket: &Socket) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    // SAFETY: the option value points at `on`, which outlives the call,
+    // and the length is its size.
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            (&on as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reads a datagram with `recvmsg`, returning its length and the kernel's
+/// timestamp from the control messages, if there was one.
+#[cfg(target_os = "linux")]
+fn recv_timestamped(socket: &Socket, buffer: &mut [u8]) -> io::Result<(usize, Option<Duration>)> {
+    let mut iov = libc::iovec {
+        iov_base: buffer.as_mut_ptr().cast(),
+        iov_len: buffer.len(),
+    };
+    // Room for a few control messages, aligned for `cmsghdr`.
+    let mut control = [0u64; 16];
+    // SAFETY: all zeros is a valid `msghdr`: no address, data or control.
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    // SAFETY: `msg` points at `iov`, `buffer` and `control`, which outlive
+    // the call, with their real lengths.
+    let bytes_read = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if bytes_read < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut timestamp = None;
+    // SAFETY: the kernel wrote `msg_controllen` bytes of well-formed control
+    // messages to `control`, and the CMSG macros stay within them. The
+    // timestamp is read unaligned as `CMSG_DATA` needn't be aligned for it.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS
+            {
+                let ts: libc::timespec = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+                timestamp = Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((bytes_read as usize, timestamp))
+}
+
+/// Platforms without `SO_TIMESTAMPNS` leave the timestamp to the caller.
+#[cfg(not(target_os = "linux"))]
+fn recv_timestamped(socket: &Socket, buffer: &mut [u8]) -> io::Result<(usize, Option<Duration>)> {
+    Ok(((&*socket).read(buffer)?, None))
+}
+
+/// The local address the kernel routes `dst` from, i.e. the source of
+/// anything a raw socket sends there without choosing one itself.
+///
+/// TCP checksums cover the IP addresses, so a segment checksummed for the
+/// wrong source (say `0.0.0.0` while bound to all interfaces) is dropped by
+/// the peer. Connecting a UDP socket runs the route lookup without sending
+/// anything.
+pub fn source_ip_for(dst: Ipv4Addr) -> io::Result<Ipv4Addr> {
+    let probe = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    probe.connect((dst, 9))?;
+    match probe.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(io::ErrorKind::AddrNotAvailable.into()),
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for RawTcpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// A `RawTcpSocket` for async code: sends and receives wait on Tokio's
+/// reactor instead of blocking a thread, so thousands of probes can be in
+/// flight from one task.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct AsyncRawTcpSocket {
+    inner: AsyncFd<RawTcpSocket>,
+}
+
+#[cfg(unix)]
+impl AsyncRawTcpSocket {
+    /// Switches `socket` to non-blocking mode and registers it with the
+    /// reactor. Has to be called from inside a Tokio runtime with IO
+    /// enabled.
+    pub fn new(socket: RawTcpSocket) -> io::Result<Self> {
+        socket.socket.set_nonblocking(true)?;
+        Ok(Self {
+            inner: AsyncFd::new(socket)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &RawTcpSocket {
+        self.inner.get_ref()
+    }
+
+    /// Like `RawTcpSocket::send`, waiting until the socket can take the
+    /// packet.
+    pub async fn send_to(&self, packet: &[u8], dst: Ipv4Addr) -> io::Result<usize> {
+        loop {
+            let mut guard = self.inner.writable().await?;
+            // On `WouldBlock` the readiness was stale: `try_io` clears it,
+            // so the next `writable` waits for a fresh event.
+            if let Ok(result) = guard.try_io(|inner| Ok(inner.get_ref().send(packet, dst)?)) {
+                return result;
+            }
+        }
+    }
+
+    /// Like `RawTcpSocket::recv`, waiting for a datagram to arrive.
+    pub async fn recv_from(&self) -> io::Result<(Vec<u8>, SocketAddr)> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            if let Ok(result) = guard.try_io(|inner| Ok(inner.get_ref().recv()?)) {
+                return result;
+            }
+        }
+    }
+}
+
+/// macOS, like FreeBSD before 11, wants the total length and fragment
+/// offset fields of an `IP_HDRINCL` datagram in host byte order.
+fn bsd_hdrincl_order(datagram: &[u8]) -> Vec<u8> {
+    let mut datagram = datagram.to_vec();
+    if datagram.len() >= 8 {
+        for field in [2..4, 6..8] {
+            let value = u16::from_be_bytes(datagram[field.clone()].try_into().unwrap());
+            datagram[field].copy_from_slice(&value.to_ne_bytes());
+        }
+    }
+    datagram
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_permission_denied_hints_at_privileges() {
+        let err = RawSocketError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(matches!(err, RawSocketError::PermissionDenied(_)));
+        assert!(err.to_string().contains("CAP_NET_RAW"));
+
+        let err = RawSocketError::from(io::Error::from(io::ErrorKind::AddrInUse));
+        assert!(matches!(err, RawSocketError::Io(_)));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn test_bsd_hdrincl_order() {
+        let datagram = [0x45, 0x00, 0x00, 0x28, 0x12, 0x34, 0x40, 0x00, 0x40, 0x06];
+        let reordered = bsd_hdrincl_order(&datagram);
+
+        assert_eq!(reordered[2..4], 0x28u16.to_ne_bytes());
+        assert_eq!(reordered[6..8], 0x4000u16.to_ne_bytes());
+        assert_eq!(reordered[4..6], datagram[4..6]);
+        assert_eq!(reordered[8..], datagram[8..]);
+    }
+
+    #[test]
+    fn test_recv_with_timestamp() {
+        let socket = match RawTcpSocket::new() {
+            Ok(socket) => socket,
+            // Raw sockets need root, which tests may not have.
+            Err(RawSocketError::PermissionDenied(_)) => return,
+            Err(e) => panic!("{}", e),
+        };
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let ip = Ipv4Addr::LOCALHOST;
+        let segment = crate::tcp::Tcp::builder()
+            .source_port(49320)
+            .dest_port(9)
+            .flags(crate::flags::TcpFlags::RST)
+            .build_packet(ip, ip);
+        socket.send(&segment, ip).unwrap();
+
+        let (datagram, src, timestamp) = socket.recv_with_timestamp().unwrap();
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert!(datagram.len() >= 40);
+        assert_eq!(src.ip(), IpAddr::V4(ip));
+        assert!(before <= timestamp && timestamp <= after);
+    }
+
+    #[test]
+    fn test_source_ip_for_loopback() {
+        assert_eq!(
+            source_ip_for(Ipv4Addr::LOCALHOST).unwrap(),
+            Ipv4Addr::LOCALHOST
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Retransmission of unacknowledged segments, with the retransmission
+//! timeout (RTO) computed as in RFC 6298 (Jacobson/Karels).
+//!
+//! Every round trip measured gives a sample `R`, smoothed into
+//!
+//! ```text
+//! RTTVAR = 3/4 * RTTVAR + 1/4 * |SRTT - R|
+//! SRTT   = 7/8 * SRTT + 1/8 * R
+//! RTO    = SRTT + 4 * RTTVAR
+//! ```
+//!
+//! and each expiry doubles the RTO until an ACK brings a new sample. As Karn
+//! noted, a retransmitted segment says nothing about the round trip, so it
+//! is never sampled.
+
+use crate::seq::seq_leq;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// RTO before the first sample.
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+/// RFC 6298 rounds the RTO up to one second.
+const MIN_RTO: Duration = Duration::from_secs(1);
+/// Backing off stops here.
+pub const MAX_RTO: Duration = Duration::from_secs(60);
+/// Times a segment is sent again before the peer is given up on, as Linux's
+/// default `tcp_retries2`.
+pub const MAX_RETRANSMITS: u32 = 15;
+
+#[derive(Debug)]
+struct Unacked {
+    seq: u32,
+    /// Sequence number just past the segment, what an ACK must reach.
+    end: u32,
+    packet: Vec<u8>,
+    sent_at: Instant,
+    expires_at: Instant,
+    retransmits: u32,
+}
+
+/// Segments sent but not acknowledged yet, in sending order.
+#[derive(Debug)]
+pub struct RetransmitQueue {
+    unacked: VecDeque<Unacked>,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl Default for RetransmitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetransmitQueue {
+    pub fn new() -> Self {
+        Self {
+            unacked: VecDeque::new(),
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+        }
+    }
+
+    /// The current retransmission timeout.
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// The smoothed round trip time, once one has been measured.
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+
+    pub fn len(&self) -> usize {
+        self.unacked.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unacked.is_empty()
+    }
+
+    /// Keeps `packet`, occupying `len` sequence numbers from `seq`, until it
+    /// is acknowledged. Its timer starts at `now`.
+    pub fn push(&mut self, seq: u32, len: u32, packet: Vec<u8>, now: Instant) {
+        self.unacked.push_back(Unacked {
+            seq,
+            end: seq.wrapping_add(len),
+            packet,
+            sent_at: now,
+            expires_at: now + self.rto,
+            retransmits: 0,
+        });
+    }
+
+    /// Drops every segment `ack` fully covers, sampling the round trip from
+    /// the newest one that was never retransmitted.
+    pub fn on_ack(&mut self, ack: u32, now: Instant) {
+        let mut sample = None;
+        while let Some(front) = self.unacked.front() {
+            if !seq_leq(front.end, ack) {
+                break;
+            }
+            if front.retransmits == 0 {
+                sample = Some(now.saturating_duration_since(front.sent_at));
+            }
+            self.unacked.pop_front();
+        }
+
+        if let Some(rtt) = sample {
+            self.update_rto(rtt);
+        }
+    }
+
+    /// Once a timer has fired by `now`, returns the oldest unacknowledged
+    /// segment to be sent again and restarts the expired timers with the
+    /// RTO doubled (up to `MAX_RTO`).
+    ///
+    /// As RFC 6298 (5.4) says, only that one segment goes out again: the
+    /// peer may well hold the ones after it, and its ACK will tell. Once
+    /// `timed_out`, nothing is sent any more.
+    pub fn resend_expired(&mut self, now: Instant) -> Option<&[u8]> {
+        if self.timed_out(now) || !self.unacked.iter().any(|s| s.expires_at <= now) {
+            return None;
+        }
+
+        self.rto = (self.rto * 2).min(MAX_RTO);
+        let rto = self.rto;
+        for segment in self.unacked.iter_mut().filter(|s| s.expires_at <= now) {
+            segment.expires_at = now + rto;
+        }
+        let oldest = self.unacked.front_mut()?;
+        oldest.retransmits += 1;
+        Some(&oldest.packet[..])
+    }
+
+    /// Whether the oldest segment's timer fired by `now` after it had
+    /// already been sent again `MAX_RETRANSMITS` times: the peer is gone.
+    pub fn timed_out(&self, now: Instant) -> bool {
+        self.unacked
+            .front()
+            .is_some_and(|s| s.retransmits >= MAX_RETRANSMITS && s.expires_at <= now)
+    }
+
+    /// When the earliest timer fires, if anything is waiting.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.unacked.iter().map(|s| s.expires_at).min()
+    }
+
+    /// Sequence numbers of the segments still waiting, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = u32> + '_ {
+        self.unacked.iter().map(|s| s.seq)
+    }
+
+    fn update_rto(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = self.rttvar * 3 / 4 + delta / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+
+        let rto = self.srtt.unwrap_or_default() + 4 * self.rttvar;
+        self.rto = rto.clamp(MIN_RTO, MAX_RTO);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_rto_from_samples() {
+        let start = Instant::now();
+        let mut queue = RetransmitQueue::new();
+        assert_eq!(queue.rto(), INITIAL_RTO);
+
+        queue.push(1000, 100, vec![1], start);
+        queue.on_ack(1100, start + ms(800));
+        assert_eq!(queue.srtt(), Some(ms(800)));
+        assert_eq!(queue.rttvar(), ms(400));
+        assert_eq!(queue.rto(), ms(800 + 4 * 400));
+
+        // RTTVAR = 3/4 * 400 + 1/4 * |800 - 400|, SRTT = 7/8 * 800 + 1/8 * 400.
+        queue.push(1100, 100, vec![2], start + ms(1000));
+        queue.on_ack(1200, start + ms(1400));
+        assert_eq!(queue.rttvar(), ms(400));
+        assert_eq!(queue.srtt(), Some(ms(750)));
+        assert_eq!(queue.rto(), ms(750 + 4 * 400));
+
+        // Short round trips still wait a second.
+        let mut queue = RetransmitQueue::new();
+        queue.push(0, 1, vec![3], start);
+        queue.on_ack(1, start + ms(10));
+        assert_eq!(queue.srtt(), Some(ms(10)));
+        assert_eq!(queue.rto(), MIN_RTO);
+    }
+
+    #[test]
+    fn test_resend_expired_backs_off() {
+        let start = Instant::now();
+        let mut queue = RetransmitQueue::new();
+        queue.push(0, 10, vec![0xAA], start);
+
+        assert_eq!(queue.next_expiry(), Some(start + ms(1000)));
+        assert!(queue.resend_expired(start + ms(999)).is_none());
+        assert_eq!(queue.resend_expired(start + ms(1000)), Some(&[0xAA][..]));
+        assert_eq!(queue.rto(), ms(2000));
+
+        assert!(queue.resend_expired(start + ms(2999)).is_none());
+        assert!(queue.resend_expired(start + ms(3000)).is_some());
+        assert_eq!(queue.rto(), ms(4000));
+
+        let mut now = start + ms(3000);
+        for _ in 0..10 {
+            now += queue.rto();
+            assert!(queue.resend_expired(now).is_some());
+        }
+        assert_eq!(queue.rto(), MAX_RTO);
+    }
+
+    #[test]
+    fn test_resend_expired_sends_only_the_oldest() {
+        let start = Instant::now();
+        let mut queue = RetransmitQueue::new();
+        queue.push(0, 10, vec![1], start);
+        queue.push(10, 10, vec![2], start);
+        queue.push(20, 10, vec![3], start);
+
+        // The first segment arrived, the second was lost.
+        queue.on_ack(10, start + ms(100));
+        assert_eq!(queue.resend_expired(start + ms(1000)), Some(&[2][..]));
+        assert_eq!(queue.next_expiry(), Some(start + ms(1000) + queue.rto()));
+
+        // The peer held the third, so one ACK covers both.
+        queue.on_ack(30, start + ms(1100));
+        assert!(queue.is_empty());
+        assert!(queue.resend_expired(start + ms(5000)).is_none());
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retransmits() {
+        let mut now = Instant::now();
+        let mut queue = RetransmitQueue::new();
+        queue.push(0, 10, vec![1], now);
+
+        for _ in 0..MAX_RETRANSMITS {
+            now = queue.next_expiry().unwrap();
+            assert!(!queue.timed_out(now));
+            assert!(queue.resend_expired(now).is_some());
+        }
+        assert_eq!(queue.rto(), MAX_RTO);
+
+        now = queue.next_expiry().unwrap();
+        assert!(!queue.timed_out(now - ms(1)));
+        assert!(queue.timed_out(now));
+        assert!(queue.resend_expired(now).is_none());
+
+        // An ACK, however late, still counts.
+        queue.on_ack(10, now);
+        assert!(!queue.timed_out(now));
+    }
+
+    #[test]
+    fn test_ack_drops_covered_segments() {
+        let start = Instant::now();
+        let mut queue = RetransmitQueue::new();
+        queue.push(u32::MAX - 9, 10, vec![1], start);
+        queue.push(0, 10, vec![2], start);
+        queue.push(10, 10, vec![3], start);
+
+        // A partial ACK of the second segment drops only the first, even
+        // across the wrap.
+        queue.on_ack(5, start + ms(100));
+        assert_eq!(queue.pending().collect::<Vec<_>>(), [0, 10]);
+
+        queue.on_ack(20, start + ms(200));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_retransmitted_segment_is_not_sampled() {
+        let start = Instant::now();
+        let mut queue = RetransmitQueue::new();
+        queue.push(0, 10, vec![1], start);
+        queue.resend_expired(start + ms(1000));
+
+        queue.on_ack(10, start + ms(1050));
+        assert_eq!(queue.srtt(), None);
+        assert_eq!(queue.rto(), ms(2000));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::checksum;
+use crate::error::TcpParseError;
+use crate::hexdump::hexdump_at;
+use crate::tcp::Tcp;
+use alloc::vec::Vec;
+use core::{fmt, net::Ipv4Addr};
+
+/// A whole TCP segment: the header together with the payload it carries,
+/// which the checksum covers. `Tcp` on its own is only the header.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    pub header: Tcp,
+    pub payload: Vec<u8>,
+}
+
+impl Segment {
+    /// Parses a segment into its header and the bytes after the header and
+    /// its options. Fails as `Tcp::parse_packet_raw` does.
+    pub fn parse(bytes: &[u8]) -> Result<Segment, TcpParseError> {
+        let (header, payload) = Tcp::parse_packet_raw(bytes)?;
+        Ok(Segment {
+            header,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// The header followed by the payload, as sent on the wire.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.header.build_packet(&self.payload)
+    }
+
+    /// Recomputes the header's checksum over the payload, for a segment
+    /// that was changed or is being sent between other addresses.
+    pub fn recompute_checksum(&mut self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) {
+        self.header
+            .recompute_checksum(src_ip, dst_ip, &self.payload);
+    }
+
+    /// Whether the header's checksum matches it and the payload.
+    pub fn verify_checksum(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> bool {
+        self.header.verify_checksum(src_ip, dst_ip, &self.payload)
+    }
+}
+
+/// Rewrites the source of `segment`, sent from `old_src_ip`, to
+/// `new_src_ip` and `new_src_port`, as a NAT does on the way out. The
+/// checksum is patched for the changed words (RFC 1624) instead of being
+/// summed over the payload again, so it stays right if it was right before.
+///
+/// The IP header's checksum covers the address too: change it with
+/// `Ipv4Header::set_src`, which patches it the same way.
+pub fn rewrite_src(
+    segment: &mut Segment,
+    new_src_ip: Ipv4Addr,
+    new_src_port: u16,
+    old_src_ip: Ipv4Addr,
+) {
+    let header = &mut segment.header;
+    header.checksum = checksum::update_address(header.checksum, old_src_ip, new_src_ip);
+    header.set_source_port(new_src_port);
+}
+
+/// Parses each of `frames` as one whole segment, in order.
+///
+/// TCP has no length field: a header says where the payload starts, but
+/// only the layer below knows where it ends. So segments can't be split out
+/// of a buffer holding several back to back; the caller has to frame them,
+/// usually by the IPv4 total length (see `Ipv4Header::parse_packet_raw`),
+/// and every frame must hold exactly one segment. A frame that doesn't
+/// parse gives its error without stopping the rest.
+pub fn parse_framed<'a>(
+    frames: impl IntoIterator<Item = &'a [u8]>,
+) -> Vec<Result<Segment, TcpParseError>> {
+    frames.into_iter().map(Segment::parse).collect()
+}
+
+impl fmt::Display for Segment {
+    /// The header as `Tcp` prints it, then the payload as a hexdump, its
+    /// offsets counted from the start of the segment.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header)?;
+        if !self.payload.is_empty() {
+            write!(
+                f,
+                "\nPayload ({} bytes):\n{}",
+                self.payload.len(),
+                hexdump_at(&self.payload, self.header.header_len())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::checksum::PROTOCOL_TCP;
+    use crate::flags::TcpFlags;
+    use crate::ip::{build_ipv4_tcp, verify_header_checksum, Ipv4Header};
+
+    #[test]
+    fn test_rewrite_src() {
+        let (private, public, server) = (
+            Ipv4Addr::new(192, 168, 1, 20),
+            Ipv4Addr::new(203, 0, 113, 7),
+            Ipv4Addr::new(198, 51, 100, 1),
+        );
+        let tcp = Tcp::builder()
+            .source_port(51000)
+            .dest_port(443)
+            .flags(TcpFlags::PSH_ACK)
+            .ack_num(1)
+            .build(private, server, b"odd-length body");
+        let datagram = build_ipv4_tcp(
+            Ipv4Header::new(private, server, PROTOCOL_TCP),
+            &tcp,
+            b"odd-length body",
+        );
+
+        let (mut ip, _, transport) = Ipv4Header::parse_packet_raw(&datagram).unwrap();
+        let mut segment = Segment::parse(transport).unwrap();
+        rewrite_src(&mut segment, public, 61000, ip.src);
+        ip.set_src(public);
+
+        assert_eq!(segment.header.source_port, 61000);
+        assert!(segment.verify_checksum(public, server));
+        assert!(verify_header_checksum(&ip.to_bytes()));
+        assert_eq!(ip.checksum, ip.calculate_checksum());
+
+        // The same as summing everything again.
+        let mut recomputed = segment.clone();
+        recomputed.recompute_checksum(public, server);
+        assert_eq!(segment.header.checksum, recomputed.header.checksum);
+    }
+
+    #[test]
+    fn test_parse_framed() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = Tcp::builder();
+        builder
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::ACK);
+        let first = builder.build(ip, ip, b"one").build_packet(b"one");
+        let second = builder.build(ip, ip, b"").build_packet(b"");
+
+        // Back to back in one buffer, framed by their lengths.
+        let mut buffer = first.clone();
+        buffer.extend_from_slice(&[0xFF; 4]);
+        buffer.extend_from_slice(&second);
+        let frames = [
+            &buffer[..first.len()],
+            &buffer[first.len()..first.len() + 4],
+            &buffer[first.len() + 4..],
+        ];
+
+        let segments = parse_framed(frames);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].as_ref().unwrap().payload, b"one");
+        assert_eq!(segments[1], Err(TcpParseError::TooShort { got: 4 }));
+        assert!(segments[2].as_ref().unwrap().payload.is_empty());
+    }
+
+    #[test]
+    fn test_segment_round_trip() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut segment = Tcp::builder()
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::PSH_ACK)
+            .ack_num(1)
+            .mss(1460)
+            .build(ip, ip, b"hello")
+            .with_payload(b"hello");
+        assert!(segment.verify_checksum(ip, ip));
+
+        let bytes = segment.to_bytes();
+        assert_eq!(Segment::parse(&bytes).unwrap(), segment);
+
+        segment.payload.extend_from_slice(b", world");
+        assert!(!segment.verify_checksum(ip, ip));
+        segment.recompute_checksum(ip, ip);
+        assert!(segment.verify_checksum(ip, ip));
+        assert_eq!(Segment::parse(&segment.to_bytes()).unwrap(), segment);
+    }
+
+    #[test]
+    fn test_segment_display_dumps_payload() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let segment = Tcp::builder()
+            .source_port(1)
+            .dest_port(2)
+            .build(ip, ip, b"hi")
+            .with_payload(b"hi");
+        let shown = segment.to_string();
+        assert!(shown.starts_with("TCP Header:"));
+        assert!(shown.ends_with(
+            "Payload (2 bytes):\n00000014: 6869                                     hi\n"
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Sequence number comparisons that survive wraparound.
+//!
+//! Sequence numbers live in a 32-bit space that wraps past `u32::MAX`, so
+//! plain `<` breaks on long-lived connections. Following RFC 1323 (serial
+//! number arithmetic, RFC 1982), `a` is before `b` when the signed distance
+//! from `b` to `a` is negative, which holds as long as the two are less than
+//! 2^31 apart.
+//!
+//! `initial_seq` picks where a connection's sequence space starts.
+
+use core::{net::Ipv4Addr, time::Duration};
+
+/// `a < b` in sequence space.
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// `a <= b` in sequence space.
+pub fn seq_leq(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+/// `a > b` in sequence space.
+pub fn seq_gt(a: u32, b: u32) -> bool {
+    seq_lt(b, a)
+}
+
+/// `a >= b` in sequence space.
+pub fn seq_geq(a: u32, b: u32) -> bool {
+    seq_leq(b, a)
+}
+
+/// An initial sequence number for a connection, per RFC 6528:
+/// `ISN = M + F(localip, localport, remoteip, remoteport, secretkey)`.
+///
+/// `M` is `now` (time since any fixed point, e.g. the Unix epoch) counted
+/// in 4 microsecond ticks, so ISNs for a given 4-tuple keep moving forward
+/// and a new incarnation of a connection starts past the old one. `F` is
+/// SipHash-2-4 of the 4-tuple keyed with `key`, which hides `M` from anyone
+/// who doesn't know the key and so keeps off-path attackers from guessing
+/// ISNs. `key` should be 16 random bytes chosen once; a shorter one is
+/// padded with zeros and the bytes past 16 are hashed with the 4-tuple.
+pub fn initial_seq(
+    src_ip: Ipv4Addr,
+    src_port: u16,
+    dst_ip: Ipv4Addr,
+    dst_port: u16,
+    key: &[u8],
+    now: Duration,
+) -> u32 {
+    let split = key.len().min(16);
+    let mut sip_key = [0u8; 16];
+    sip_key[..split].copy_from_slice(&key[..split]);
+
+    let mut message = key[split..].to_vec();
+    message.extend_from_slice(&src_ip.octets());
+    message.extend_from_slice(&src_port.to_be_bytes());
+    message.extend_from_slice(&dst_ip.octets());
+    message.extend_from_slice(&dst_port.to_be_bytes());
+
+    let k0 = u64::from_le_bytes(sip_key[..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(sip_key[8..].try_into().unwrap());
+    let ticks = (now.as_micros() / 4) as u32;
+    ticks.wrapping_add(siphash24(k0, k1, &message) as u32)
+}
+
+/// SipHash-2-4 (Aumasson and Bernstein), a keyed hash that is safe to
+/// expose the output of.
+fn siphash24(k0: u64, k1: u64, message: &[u8]) -> u64 {
+    fn sip_round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+
+    fn compress(v: &mut [u64; 4], m: u64) {
+        v[3] ^= m;
+        sip_round(v);
+        sip_round(v);
+        v[0] ^= m;
+    }
+
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+
+    let words = message.chunks_exact(8);
+    let tail = words.remainder();
+    for word in words {
+        compress(&mut v, u64::from_le_bytes(word.try_into().unwrap()));
+    }
+    // The last word holds the leftover bytes and the length's low byte.
+    let mut last = [0u8; 8];
+    last[..tail.len()].copy_from_slice(tail);
+    compress(
+        &mut v,
+        u64::from_le_bytes(last) | (message.len() as u64) << 56,
+    );
+
+    v[2] ^= 0xFF;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_seq_without_wrap() {
+        assert!(seq_lt(1, 2));
+        assert!(!seq_lt(2, 1));
+        assert!(!seq_lt(2, 2));
+        assert!(seq_leq(2, 2));
+        assert!(seq_gt(2, 1));
+        assert!(seq_geq(2, 2));
+    }
+
+    #[test]
+    fn test_seq_across_wrap() {
+        assert!(seq_lt(0xFFFF_FFFF, 0));
+        assert!(seq_lt(0xFFFF_FFF0, 0x10));
+        assert!(seq_gt(0, 0xFFFF_FFFF));
+        assert!(seq_leq(0xFFFF_FFFF, 0));
+        assert!(!seq_geq(0xFFFF_FFFF, 0));
+    }
+
+    #[test]
+    fn test_seq_half_space() {
+        // Just under 2^31 apart still compares as expected.
+        assert!(seq_lt(0, 0x7FFF_FFFF));
+        assert!(seq_gt(0, 0x8000_0001));
+    }
+
+    #[test]
+    fn test_siphash24_reference_vectors() {
+        // From the SipHash paper: key 00..0f, messages 00..(n-1).
+        let (k0, k1) = (0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+        let message: alloc::vec::Vec<u8> = (0..15).collect();
+        assert_eq!(siphash24(k0, k1, &[]), 0x726f_db47_dd0e_0e31);
+        assert_eq!(siphash24(k0, k1, &message), 0xa129_ca61_49be_45e5);
+    }
+
+    #[test]
+    fn test_initial_seq() {
+        let (a, b) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+        let key = [7u8; 16];
+        let now = Duration::from_secs(1_700_000_000);
+        let isn = initial_seq(a, 49320, b, 80, &key, now);
+
+        assert_eq!(initial_seq(a, 49320, b, 80, &key, now), isn);
+        assert_ne!(initial_seq(a, 49321, b, 80, &key, now), isn);
+        assert_ne!(initial_seq(b, 49320, a, 80, &key, now), isn);
+        assert_ne!(initial_seq(a, 49320, b, 80, &[8u8; 16], now), isn);
+
+        // The clock moves the ISN on by one every 4 microseconds.
+        let later = now + Duration::from_micros(400);
+        assert_eq!(
+            initial_seq(a, 49320, b, 80, &key, later),
+            isn.wrapping_add(100)
+        );
+    }
+}
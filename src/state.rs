@@ -0,0 +1,323 @@
+use crate::error::InvalidTransition;
+use crate::flags::TcpFlags;
+use core::fmt;
+
+/// Connection states from the RFC 793 state diagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+impl ConnectionState {
+    /// Flags of the segment sent on the way into this state, following the
+    /// diagram on `TcpStateMachine`; empty where nothing is sent.
+    pub fn flag(&self) -> TcpFlags {
+        use ConnectionState::*;
+
+        match self {
+            Closed | Listen | FinWait2 => TcpFlags::empty(),
+            SynSent => TcpFlags::SYN,
+            SynReceived => TcpFlags::SYN_ACK,
+            Established | CloseWait | Closing | TimeWait => TcpFlags::ACK,
+            FinWait1 | LastAck => TcpFlags::FIN_ACK,
+        }
+    }
+}
+
+/// The state's name as RFC 793 writes it, e.g. `SYN-RECEIVED`.
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ConnectionState::*;
+
+        f.write_str(match self {
+            Closed => "CLOSED",
+            Listen => "LISTEN",
+            SynSent => "SYN-SENT",
+            SynReceived => "SYN-RECEIVED",
+            Established => "ESTABLISHED",
+            FinWait1 => "FIN-WAIT-1",
+            FinWait2 => "FIN-WAIT-2",
+            CloseWait => "CLOSE-WAIT",
+            Closing => "CLOSING",
+            LastAck => "LAST-ACK",
+            TimeWait => "TIME-WAIT",
+        })
+    }
+}
+
+/// What can happen to a connection: a user call, an incoming segment or the
+/// TIME-WAIT timer expiring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpEvent {
+    PassiveOpen,
+    ActiveOpen,
+    Close,
+    RecvSyn,
+    RecvSynAck,
+    RecvAck,
+    RecvFin,
+    /// A FIN that also acknowledges our own FIN.
+    RecvFinAck,
+    RecvRst,
+    /// The 2*MSL timer ran out.
+    Timeout,
+}
+
+/// Drives a connection through the RFC 793 state diagram.
+///
+/// ```text
+///                              +---------+ ---------\      active OPEN
+///                              |  CLOSED |            \    -----------
+///                              +---------+<---------\   \   create TCB
+///                                |     ^              \   \  snd SYN
+///                   passive OPEN |     |   CLOSE        \   \
+///                   ------------ |     | ----------       \   \
+///                    create TCB  |     | delete TCB         \   \
+///                                V     |                      \   \
+///                              +---------+            CLOSE    |    \
+///                              |  LISTEN |          ---------- |     |
+///                              +---------+          delete TCB |     |
+///                   rcv SYN      |     |     SEND              |     |
+///                  -----------   |     |    -------            |     V
+/// +---------+      snd SYN,ACK  /       \   snd SYN          +---------+
+/// |         |<-----------------           ------------------>|         |
+/// |   SYN   |                    rcv SYN                     |   SYN   |
+/// |   RCVD  |<-----------------------------------------------|   SENT  |
+/// |         |                    snd ACK                     |         |
+/// |         |------------------           -------------------|         |
+/// +---------+   rcv ACK of SYN  \       /  rcv SYN,ACK       +---------+
+///   |           --------------   |     |   -----------
+///   |                  x         |     |     snd ACK
+///   |                            V     V
+///   |  CLOSE                   +---------+
+///   | -------                  |  ESTAB  |
+///   | snd FIN                  +---------+
+///   |                   CLOSE    |     |    rcv FIN
+///   V                  -------   |     |    -------
+/// +---------+          snd FIN  /       \   snd ACK          +---------+
+/// |  FIN    |<-----------------           ------------------>|  CLOSE  |
+/// | WAIT-1  |------------------                              |   WAIT  |
+/// +---------+          rcv FIN  \                            +---------+
+///   | rcv ACK of FIN   -------   |                            CLOSE  |
+///   | --------------   snd ACK   |                           ------- |
+///   V        x                   V                           snd FIN V
+/// +---------+                  +---------+                   +---------+
+/// |FINWAIT-2|                  | CLOSING |                   | LAST-ACK|
+/// +---------+                  +---------+                   +---------+
+///   |                rcv ACK of FIN |                 rcv ACK of FIN |
+///   |  rcv FIN       -------------- |    Timeout=2MSL -------------- |
+///   |  -------              x       V    ------------        x       V
+///    \ snd ACK                 +---------+delete TCB         +---------+
+///     ------------------------>|TIME WAIT|------------------>| CLOSED  |
+///                              +---------+                   +---------+
+/// ```
+///
+/// A RST aborts any connection; in SYN-RECEIVED it returns a passively
+/// opened connection to LISTEN.
+#[derive(Debug)]
+pub struct TcpStateMachine {
+    state: ConnectionState,
+    /// Whether the connection came from LISTEN, where a RST returns it.
+    passive: bool,
+}
+
+impl Default for TcpStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TcpStateMachine {
+    pub fn new() -> Self {
+        Self {
+            state: ConnectionState::Closed,
+            passive: false,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Applies `event` and returns the new state, or an error leaving the
+    /// state untouched if the diagram has no such edge.
+    pub fn transition(&mut self, event: TcpEvent) -> Result<ConnectionState, InvalidTransition> {
+        use ConnectionState::*;
+        use TcpEvent::*;
+
+        let next = match (self.state, event) {
+            (Closed, PassiveOpen) => {
+                self.passive = true;
+                Listen
+            }
+            (Closed, ActiveOpen) => {
+                self.passive = false;
+                SynSent
+            }
+            (Listen, RecvSyn) => SynReceived,
+            (Listen, Close) => Closed,
+            (SynSent, RecvSyn) => SynReceived,
+            (SynSent, RecvSynAck) => Established,
+            (SynSent, Close) => Closed,
+            (SynReceived, RecvAck) => Established,
+            (SynReceived, Close) => FinWait1,
+            (SynReceived, RecvRst) if self.passive => Listen,
+            (Established, Close) => FinWait1,
+            (Established, RecvFin) => CloseWait,
+            (FinWait1, RecvAck) => FinWait2,
+            (FinWait1, RecvFin) => Closing,
+            (FinWait1, RecvFinAck) => TimeWait,
+            (FinWait2, RecvFin | RecvFinAck) => TimeWait,
+            (CloseWait, Close) => LastAck,
+            (Closing, RecvAck) => TimeWait,
+            (LastAck, RecvAck) => Closed,
+            (TimeWait, Timeout) => Closed,
+            (
+                SynSent | SynReceived | Established | FinWait1 | FinWait2 | CloseWait | Closing
+                | LastAck | TimeWait,
+                RecvRst,
+            ) => Closed,
+            (from, event) => return Err(InvalidTransition { from, event }),
+        };
+
+        self.state = next;
+        Ok(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn drive(events: &[TcpEvent]) -> TcpStateMachine {
+        let mut machine = TcpStateMachine::new();
+        for event in events {
+            machine.transition(*event).unwrap();
+        }
+        machine
+    }
+
+    #[test]
+    fn test_passive_open() {
+        let machine = drive(&[TcpEvent::PassiveOpen, TcpEvent::RecvSyn, TcpEvent::RecvAck]);
+        assert_eq!(machine.state(), ConnectionState::Established);
+    }
+
+    #[test]
+    fn test_active_open() {
+        let machine = drive(&[TcpEvent::ActiveOpen, TcpEvent::RecvSynAck]);
+        assert_eq!(machine.state(), ConnectionState::Established);
+    }
+
+    #[test]
+    fn test_active_close() {
+        let mut machine = drive(&[TcpEvent::ActiveOpen, TcpEvent::RecvSynAck]);
+
+        assert_eq!(
+            machine.transition(TcpEvent::Close),
+            Ok(ConnectionState::FinWait1)
+        );
+        assert_eq!(
+            machine.transition(TcpEvent::RecvAck),
+            Ok(ConnectionState::FinWait2)
+        );
+        assert_eq!(
+            machine.transition(TcpEvent::RecvFin),
+            Ok(ConnectionState::TimeWait)
+        );
+        assert_eq!(
+            machine.transition(TcpEvent::Timeout),
+            Ok(ConnectionState::Closed)
+        );
+    }
+
+    #[test]
+    fn test_passive_close() {
+        let mut machine = drive(&[TcpEvent::ActiveOpen, TcpEvent::RecvSynAck]);
+
+        assert_eq!(
+            machine.transition(TcpEvent::RecvFin),
+            Ok(ConnectionState::CloseWait)
+        );
+        assert_eq!(
+            machine.transition(TcpEvent::Close),
+            Ok(ConnectionState::LastAck)
+        );
+        assert_eq!(
+            machine.transition(TcpEvent::RecvAck),
+            Ok(ConnectionState::Closed)
+        );
+    }
+
+    #[test]
+    fn test_simultaneous_close() {
+        let mut machine = drive(&[TcpEvent::ActiveOpen, TcpEvent::RecvSynAck, TcpEvent::Close]);
+
+        assert_eq!(
+            machine.transition(TcpEvent::RecvFin),
+            Ok(ConnectionState::Closing)
+        );
+        assert_eq!(
+            machine.transition(TcpEvent::RecvAck),
+            Ok(ConnectionState::TimeWait)
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut machine = drive(&[TcpEvent::PassiveOpen, TcpEvent::RecvSyn]);
+        assert_eq!(
+            machine.transition(TcpEvent::RecvRst),
+            Ok(ConnectionState::Listen)
+        );
+
+        let mut machine = drive(&[TcpEvent::ActiveOpen, TcpEvent::RecvSynAck]);
+        assert_eq!(
+            machine.transition(TcpEvent::RecvRst),
+            Ok(ConnectionState::Closed)
+        );
+    }
+
+    #[test]
+    fn test_invalid_transition() {
+        let mut machine = drive(&[TcpEvent::PassiveOpen]);
+
+        assert_eq!(
+            machine.transition(TcpEvent::RecvAck),
+            Err(InvalidTransition {
+                from: ConnectionState::Listen,
+                event: TcpEvent::RecvAck,
+            })
+        );
+        assert_eq!(machine.state(), ConnectionState::Listen);
+    }
+
+    #[test]
+    fn test_state_display_and_flag() {
+        assert_eq!(ConnectionState::SynReceived.to_string(), "SYN-RECEIVED");
+        assert_eq!(ConnectionState::FinWait2.to_string(), "FIN-WAIT-2");
+        assert_eq!(
+            InvalidTransition {
+                from: ConnectionState::Listen,
+                event: TcpEvent::RecvAck,
+            }
+            .to_string(),
+            "invalid event RecvAck in state LISTEN"
+        );
+
+        assert_eq!(ConnectionState::SynReceived.flag(), TcpFlags::SYN_ACK);
+        assert_eq!(ConnectionState::LastAck.flag(), TcpFlags::FIN_ACK);
+        assert!(ConnectionState::Listen.flag().is_empty());
+    }
+}
@@ -0,0 +1,443 @@
+//! The byte stream on top of an established connection, without any I/O:
+//! payloads are cut into segments on the way out and put back in order on
+//! the way in.
+
+use crate::flags::TcpFlags;
+use crate::options::TcpOption;
+use crate::seq::{seq_gt, seq_leq, seq_lt};
+use crate::tcp::{Tcp, TcpBuilder};
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+
+/// MSS to assume when the peer doesn't announce one (RFC 1122).
+pub const DEFAULT_MSS: u16 = 536;
+
+/// The MSS to use towards a peer whose SYN carried `options`: the smaller
+/// of ours and theirs.
+pub fn negotiate_mss(ours: u16, options: &[TcpOption]) -> u16 {
+    let theirs = options.iter().find_map(|option| match option {
+        TcpOption::Mss(mss) => Some(*mss),
+        _ => None,
+    });
+    ours.min(theirs.unwrap_or(DEFAULT_MSS)).max(1)
+}
+
+/// Sequence space bookkeeping for one direction-pair of a connection.
+#[derive(Debug)]
+pub struct Stream {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    /// Next sequence number to send (SND.NXT).
+    snd_nxt: u32,
+    /// Next sequence number expected from the peer (RCV.NXT).
+    rcv_nxt: u32,
+    mss: u16,
+    /// Segments that arrived ahead of a gap, by sequence number.
+    out_of_order: Vec<(u32, Vec<u8>)>,
+    /// Where the peer's FIN sits, once seen.
+    fin_seq: Option<u32>,
+    fin_received: bool,
+    /// The window the peer last announced (SND.WND), unlimited until then.
+    snd_wnd: u32,
+    /// Data held back while the peer's window is zero.
+    unsent: Vec<u8>,
+}
+
+impl Stream {
+    pub fn new(
+        (src, src_port): (Ipv4Addr, u16),
+        (dst, dst_port): (Ipv4Addr, u16),
+        snd_nxt: u32,
+        rcv_nxt: u32,
+        mss: u16,
+    ) -> Self {
+        Self {
+            src,
+            dst,
+            src_port,
+            dst_port,
+            snd_nxt,
+            rcv_nxt,
+            mss: mss.max(1),
+            out_of_order: Vec::new(),
+            fin_seq: None,
+            fin_received: false,
+            snd_wnd: u32::MAX,
+            unsent: Vec::new(),
+        }
+    }
+
+    pub fn src(&self) -> (Ipv4Addr, u16) {
+        (self.src, self.src_port)
+    }
+
+    pub fn dst(&self) -> (Ipv4Addr, u16) {
+        (self.dst, self.dst_port)
+    }
+
+    pub fn seq_num(&self) -> u32 {
+        self.snd_nxt
+    }
+
+    pub fn ack_num(&self) -> u32 {
+        self.rcv_nxt
+    }
+
+    pub fn mss(&self) -> u16 {
+        self.mss
+    }
+
+    /// Whether the peer's FIN has been received in order, i.e. everything it
+    /// will ever send has been delivered.
+    pub fn fin_received(&self) -> bool {
+        self.fin_received
+    }
+
+    /// The peer's receive window, from its latest segment.
+    pub fn send_window(&self) -> u32 {
+        self.snd_wnd
+    }
+
+    /// Sets the peer's receive window, as announced on its SYN-ACK.
+    pub fn set_send_window(&mut self, window: u32) {
+        self.snd_wnd = window;
+    }
+
+    /// Bytes waiting for the peer's window to open.
+    pub fn unsent(&self) -> usize {
+        self.unsent.len()
+    }
+
+    /// Cuts `data` into segments of at most one MSS, PSH set on the last,
+    /// and advances the sequence number past them. Returns each segment as
+    /// bytes (header and payload) ready for the wire.
+    ///
+    /// While the peer's window is zero nothing is sent: `data` is queued
+    /// until `flush` finds the window open again.
+    pub fn segments(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.unsent.extend_from_slice(data);
+        self.flush()
+    }
+
+    /// Segments for the queued data, if the peer's window has opened.
+    pub fn flush(&mut self) -> Vec<Vec<u8>> {
+        if self.snd_wnd == 0 || self.unsent.is_empty() {
+            return Vec::new();
+        }
+
+        let data = core::mem::take(&mut self.unsent);
+        let chunks: Vec<&[u8]> = data.chunks(self.mss as usize).collect();
+        let mut segments = Vec::with_capacity(chunks.len());
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let flags = if i + 1 == chunks.len() {
+                TcpFlags::PSH_ACK
+            } else {
+                TcpFlags::ACK
+            };
+            let tcp = self.segment(flags, chunk);
+            segments.push(tcp.build_packet(chunk));
+            self.snd_nxt = self.snd_nxt.wrapping_add(chunk.len() as u32);
+        }
+
+        segments
+    }
+
+    /// A bare ACK for everything received so far.
+    pub fn ack(&self) -> Tcp {
+        self.segment(TcpFlags::ACK, b"")
+    }
+
+    /// A zero-window probe (RFC 1122 4.2.2.17): the next queued byte, sent
+    /// past the closed window so the peer's ACK reports when it reopens.
+    /// The byte only counts as sent once the peer acknowledges it.
+    pub fn zero_window_probe(&self) -> Option<Vec<u8>> {
+        if self.snd_wnd != 0 {
+            return None;
+        }
+        let byte = self.unsent.first()?;
+        let probe = self.segment(TcpFlags::ACK, core::slice::from_ref(byte));
+        Some(probe.build_packet(core::slice::from_ref(byte)))
+    }
+
+    /// A keepalive probe: an ACK one byte behind SND.NXT, for data the peer
+    /// has already acknowledged, so a live peer answers with an ACK of its
+    /// own. Like Linux, the probe carries no payload.
+    pub fn keepalive_probe(&self) -> Tcp {
+        self.segment_at(self.snd_nxt.wrapping_sub(1), TcpFlags::ACK, b"")
+    }
+
+    /// Takes in a segment from the peer, returning the bytes it makes
+    /// available in order, possibly together with earlier buffered ones.
+    ///
+    /// Duplicates and the already received part of overlapping segments
+    /// are dropped, and segments past a gap are held until it fills.
+    /// Segments from other ports are ignored.
+    pub fn receive(&mut self, tcp: &Tcp, payload: &[u8]) -> Vec<u8> {
+        if tcp.source_port != self.dst_port || tcp.dest_port != self.src_port {
+            return Vec::new();
+        }
+
+        if tcp.flags.contains(TcpFlags::ACK) {
+            if !self.unsent.is_empty() && tcp.ack_num == self.snd_nxt.wrapping_add(1) {
+                // The peer took the byte of a zero-window probe.
+                self.unsent.remove(0);
+                self.snd_nxt = self.snd_nxt.wrapping_add(1);
+            }
+            self.snd_wnd = tcp.window_size as u32;
+        }
+
+        if tcp.flags.contains(TcpFlags::FIN) {
+            self.fin_seq = Some(tcp.seq_num.wrapping_add(payload.len() as u32));
+        }
+
+        let mut delivered = Vec::new();
+        self.accept(tcp.seq_num, payload, &mut delivered);
+
+        // The new bytes may close gaps in front of buffered segments.
+        while let Some(i) = self
+            .out_of_order
+            .iter()
+            .position(|(seq, _)| seq_leq(*seq, self.rcv_nxt))
+        {
+            let (seq, data) = self.out_of_order.swap_remove(i);
+            self.accept(seq, &data, &mut delivered);
+        }
+
+        if !self.fin_received && self.fin_seq == Some(self.rcv_nxt) {
+            // The FIN takes up a sequence number of its own.
+            self.fin_received = true;
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            self.out_of_order.clear();
+        }
+
+        delivered
+    }
+
+    /// Whether `tcp` is a RST from the peer within the window we announce,
+    /// `u16::MAX` bytes from RCV.NXT (RFC 9293 3.10.7.4), which closes the
+    /// connection. RSTs outside it are stale or forged and don't count.
+    pub fn is_reset(&self, tcp: &Tcp) -> bool {
+        let window_end = self.rcv_nxt.wrapping_add(u16::MAX as u32);
+        tcp.source_port == self.dst_port
+            && tcp.dest_port == self.src_port
+            && tcp.flags.contains(TcpFlags::RST)
+            && seq_leq(self.rcv_nxt, tcp.seq_num)
+            && seq_lt(tcp.seq_num, window_end)
+    }
+
+    /// Delivers the part of `data` (starting at `seq`) past RCV.NXT if it
+    /// is next in line, or buffers it if it is ahead.
+    fn accept(&mut self, seq: u32, data: &[u8], delivered: &mut Vec<u8>) {
+        if data.is_empty() || self.fin_received {
+            return;
+        }
+        let end = seq.wrapping_add(data.len() as u32);
+        if seq_leq(end, self.rcv_nxt) {
+            return; // Duplicate.
+        }
+        if seq_gt(seq, self.rcv_nxt) {
+            if !self.out_of_order.iter().any(|(s, _)| *s == seq) {
+                self.out_of_order.push((seq, data.to_vec()));
+            }
+            return;
+        }
+
+        let skip = self.rcv_nxt.wrapping_sub(seq) as usize;
+        delivered.extend_from_slice(&data[skip..]);
+        self.rcv_nxt = end;
+    }
+
+    fn segment(&self, flags: TcpFlags, payload: &[u8]) -> Tcp {
+        self.segment_at(self.snd_nxt, flags, payload)
+    }
+
+    fn segment_at(&self, seq: u32, flags: TcpFlags, payload: &[u8]) -> Tcp {
+        TcpBuilder::new()
+            .source_port(self.src_port)
+            .dest_port(self.dst_port)
+            .seq_num(seq)
+            .ack_num(self.rcv_nxt)
+            .flags(flags)
+            .window_size(u16::MAX)
+            .build(self.src, self.dst, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const LOCAL: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 0, 0, 1), 40000);
+    const PEER: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 0, 0, 2), 80);
+
+    /// A segment from the peer carrying `payload` at `seq`.
+    fn from_peer(seq: u32, flags: TcpFlags) -> Tcp {
+        TcpBuilder::new()
+            .source_port(PEER.1)
+            .dest_port(LOCAL.1)
+            .seq_num(seq)
+            .ack_num(1)
+            .flags(flags)
+            .build(PEER.0, LOCAL.0, b"")
+    }
+
+    #[test]
+    fn test_negotiate_mss() {
+        assert_eq!(negotiate_mss(1460, &[TcpOption::Mss(1200)]), 1200);
+        assert_eq!(negotiate_mss(1460, &[TcpOption::Mss(9000)]), 1460);
+        assert_eq!(negotiate_mss(1460, &[]), DEFAULT_MSS);
+    }
+
+    #[test]
+    fn test_segments_respect_mss() {
+        let mut stream = Stream::new(LOCAL, PEER, 1000, 5000, 4);
+        let segments = stream.segments(b"0123456789");
+        assert_eq!(segments.len(), 3);
+        assert_eq!(stream.seq_num(), 1010);
+
+        let parsed: Vec<(Tcp, &[u8])> = segments
+            .iter()
+            .map(|s| Tcp::parse_packet_raw(s).unwrap())
+            .collect();
+        assert_eq!(
+            parsed.iter().map(|(_, p)| *p).collect::<Vec<_>>(),
+            [&b"0123"[..], b"4567", b"89"]
+        );
+        assert_eq!(
+            parsed.iter().map(|(t, _)| t.seq_num).collect::<Vec<_>>(),
+            [1000, 1004, 1008]
+        );
+        assert_eq!(parsed[0].0.flags, TcpFlags::ACK);
+        assert_eq!(parsed[2].0.flags, TcpFlags::PSH_ACK);
+        assert_eq!(parsed[2].0.ack_num, 5000);
+        assert!(parsed[2].0.verify_checksum(LOCAL.0, PEER.0, b"89"));
+    }
+
+    #[test]
+    fn test_receive_in_order_and_duplicates() {
+        let mut stream = Stream::new(LOCAL, PEER, 1, 100, 1460);
+        assert_eq!(
+            stream.receive(&from_peer(100, TcpFlags::ACK), b"abc"),
+            b"abc"
+        );
+        assert_eq!(stream.ack_num(), 103);
+
+        // A full duplicate, then an overlap with one new byte.
+        assert!(stream
+            .receive(&from_peer(100, TcpFlags::ACK), b"abc")
+            .is_empty());
+        assert_eq!(stream.receive(&from_peer(101, TcpFlags::ACK), b"bcd"), b"d");
+        assert_eq!(stream.ack().ack_num, 104);
+    }
+
+    #[test]
+    fn test_receive_buffers_out_of_order() {
+        let mut stream = Stream::new(LOCAL, PEER, 1, u32::MAX - 1, 1460);
+
+        // Past the gap, and across the sequence number wrap.
+        assert!(stream
+            .receive(&from_peer(2, TcpFlags::ACK), b"ef")
+            .is_empty());
+        assert!(stream
+            .receive(&from_peer(0, TcpFlags::ACK), b"cd")
+            .is_empty());
+        assert_eq!(stream.ack_num(), u32::MAX - 1);
+
+        assert_eq!(
+            stream.receive(&from_peer(u32::MAX - 1, TcpFlags::ACK), b"ab"),
+            b"abcdef"
+        );
+        assert_eq!(stream.ack_num(), 4);
+    }
+
+    #[test]
+    fn test_receive_fin_after_gap() {
+        let mut stream = Stream::new(LOCAL, PEER, 1, 100, 1460);
+        let fin = TcpFlags::FIN | TcpFlags::ACK;
+
+        assert!(stream.receive(&from_peer(102, fin), b"cd").is_empty());
+        assert!(!stream.fin_received());
+
+        assert_eq!(
+            stream.receive(&from_peer(100, TcpFlags::ACK), b"ab"),
+            b"abcd"
+        );
+        assert!(stream.fin_received());
+        assert_eq!(stream.ack_num(), 105);
+    }
+
+    #[test]
+    fn test_receive_ignores_other_ports() {
+        let mut stream = Stream::new(LOCAL, PEER, 1, 100, 1460);
+        let mut other = from_peer(100, TcpFlags::ACK);
+        other.source_port = 81;
+        assert!(stream.receive(&other, b"abc").is_empty());
+        assert_eq!(stream.ack_num(), 100);
+    }
+
+    #[test]
+    fn test_is_reset() {
+        let stream = Stream::new(LOCAL, PEER, 1, 100, 1460);
+        assert!(stream.is_reset(&from_peer(100, TcpFlags::RST)));
+        assert!(stream.is_reset(&from_peer(100 + 65534, TcpFlags::RST_ACK)));
+        assert!(!stream.is_reset(&from_peer(100 + 65535, TcpFlags::RST)));
+        assert!(!stream.is_reset(&from_peer(99, TcpFlags::RST)));
+        assert!(!stream.is_reset(&from_peer(100, TcpFlags::ACK)));
+
+        let mut other = from_peer(100, TcpFlags::RST);
+        other.source_port = 81;
+        assert!(!stream.is_reset(&other));
+    }
+
+    #[test]
+    fn test_keepalive_probe() {
+        let stream = Stream::new(LOCAL, PEER, 0, 5000, 1460);
+        let probe = stream.keepalive_probe();
+
+        // One behind SND.NXT, which wraps at 0.
+        assert_eq!(probe.seq_num, u32::MAX);
+        assert_eq!(probe.ack_num, 5000);
+        assert_eq!(probe.flags, TcpFlags::ACK);
+        assert_eq!(stream.seq_num(), 0);
+        assert!(probe.verify_checksum(LOCAL.0, PEER.0, b""));
+    }
+
+    #[test]
+    fn test_zero_window_queues_writes() {
+        let mut stream = Stream::new(LOCAL, PEER, 1000, 5000, 1460);
+        let window = |ack: u32, size: u16| {
+            let mut tcp = from_peer(5000, TcpFlags::ACK);
+            tcp.ack_num = ack;
+            tcp.window_size = size;
+            tcp
+        };
+
+        let shut = window(1000, 0);
+        assert!(shut.advertises_zero_window());
+        stream.receive(&shut, b"");
+        assert!(stream.segments(b"abc").is_empty());
+        assert_eq!(stream.unsent(), 3);
+        assert_eq!(stream.seq_num(), 1000);
+
+        // The probe carries the first byte without advancing SND.NXT.
+        let probe = stream.zero_window_probe().unwrap();
+        let (tcp, payload) = Tcp::parse_packet_raw(&probe).unwrap();
+        assert_eq!((tcp.seq_num, payload), (1000, &b"a"[..]));
+        assert_eq!(stream.seq_num(), 1000);
+
+        // The peer takes it, but its window is still shut.
+        stream.receive(&window(1001, 0), b"");
+        assert_eq!((stream.seq_num(), stream.unsent()), (1001, 2));
+        assert!(stream.flush().is_empty());
+
+        stream.receive(&window(1001, 1024), b"");
+        assert!(stream.zero_window_probe().is_none());
+        let segments = stream.flush();
+        assert_eq!(Tcp::parse_packet_raw(&segments[0]).unwrap().1, b"bc");
+        assert_eq!((stream.seq_num(), stream.unsent()), (1003, 0));
+    }
+}
@@ -1,134 +1,701 @@
 #![allow(dead_code)]
 
+pub use crate::checksum::PseudoHeader;
+use crate::checksum::{self, PROTOCOL_TCP};
+use crate::error::{BuildError, BuildWarning, ParseError, TcpParseError};
 use crate::flags::TcpFlags;
-use core::panic;
-use std::{fmt, net::Ipv4Addr};
+use crate::hexdump::{decode_hex, encode_hex};
+use crate::options::{self, TcpOption, MAX_OPTIONS_LEN};
+use crate::segment::Segment;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+    net::Ipv4Addr,
+};
 
-#[derive(Debug)]
+/// Largest window scale shift RFC 7323 allows, keeping windows under 1 GiB.
+pub const MAX_WINDOW_SCALE: u8 = 14;
+
+/// First port of the IANA dynamic range (RFC 6335), which runs to 65535.
+pub const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// Longest segment `parse_packet` accepts by default: the most an IPv4
+/// datagram can carry after its 20-byte header.
+pub const MAX_SEGMENT_SIZE: usize = 65535 - 20;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tcp {
     pub source_port: u16,
     pub dest_port: u16,
     pub seq_num: u32,
     pub ack_num: u32,
     pub flags: TcpFlags,
+    /// ECN nonce sum (RFC 3540), the ninth flag bit, which doesn't fit in
+    /// `TcpFlags`.
+    pub ns: bool,
+    /// The 3 reserved bits between the data offset and NS, which senders
+    /// must leave at 0 (RFC 9293). Kept so conformance tests can see them.
+    pub reserved: u8,
     pub window_size: u16,
     pub checksum: u16,
+    pub urgent_ptr: u16,
+    pub options: Vec<TcpOption>,
+    /// Set by `flags_mut`: the stored checksum no longer covers the header.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    checksum_dirty: bool,
+}
+
+/// Compares the header fields, checksum included. Whether the checksum went
+/// stale through `flags_mut` is bookkeeping, not part of the header, so a
+/// segment equals its reparsed bytes either way.
+impl PartialEq for Tcp {
+    fn eq(&self, other: &Self) -> bool {
+        self.equivalent_ignoring_checksum(other) && self.checksum == other.checksum
+    }
+}
+
+impl Eq for Tcp {}
+
+/// Hashes what `==` compares.
+impl Hash for Tcp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Tcp {
+            source_port,
+            dest_port,
+            seq_num,
+            ack_num,
+            flags,
+            ns,
+            reserved,
+            window_size,
+            checksum,
+            urgent_ptr,
+            options,
+            checksum_dirty: _,
+        } = self;
+
+        source_port.hash(state);
+        dest_port.hash(state);
+        seq_num.hash(state);
+        ack_num.hash(state);
+        flags.hash(state);
+        ns.hash(state);
+        reserved.hash(state);
+        window_size.hash(state);
+        checksum.hash(state);
+        urgent_ptr.hash(state);
+        options.hash(state);
+    }
 }
 
 impl fmt::Display for Tcp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let output = format!(
+    /// Prints the full header, one field per line. The alternate form
+    /// (`{:#}`) prints a single tcpdump-style line instead, e.g.
+    /// `49320 > 80 [SYN|ACK] seq=1 ack=2 win=1024`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            let names = self.flags.names();
+            let flags = if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join("|")
+            };
+            return write!(
+                f,
+                "{} > {} [{}] seq={} ack={} win={}",
+                self.source_port,
+                self.dest_port,
+                flags,
+                self.seq_num,
+                self.ack_num,
+                self.window_size
+            );
+        }
+
+        let output = alloc::format!(
             r#"TCP Header:
     Source Port: {}
     Destination Port: {}
     Sequence Number: {}
     Acknowledge Number: {}
-    Flags: {}"#,
-            self.source_port, self.dest_port, self.seq_num, self.ack_num, self.flags
+    Flags: {}
+    Window Size: {}
+    Checksum: {:#06x}
+    Urgent Pointer: {}"#,
+            self.source_port,
+            self.dest_port,
+            self.seq_num,
+            self.ack_num,
+            self.flags,
+            self.window_size,
+            self.checksum,
+            self.urgent_ptr
         );
-        write!(f, "{}", output)
+        write!(f, "{}", output)?;
+
+        if self.flags.contains(TcpFlags::URG) && self.urgent_ptr == 0 {
+            write!(f, "\n    Note: URG is set but the urgent pointer is 0")?;
+        }
+        Ok(())
     }
 }
 
 impl Tcp {
+    /// A `TcpBuilder` with the defaults of `TcpBuilder::new`.
+    ///
+    /// ```
+    /// use harbinger::{flags::TcpFlags, tcp::Tcp};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let ip = Ipv4Addr::LOCALHOST;
+    /// let syn = Tcp::builder()
+    ///     .source_port(49320)
+    ///     .dest_port(80)
+    ///     .flags(TcpFlags::SYN)
+    ///     .build(ip, ip, b"");
+    /// assert!(syn.verify_checksum(ip, ip, b""));
+    /// ```
+    pub fn builder() -> TcpBuilder {
+        TcpBuilder::new()
+    }
+
+    /// Serializes the full header: the fixed 20 bytes followed by the
+    /// options, padded to a 4-byte boundary.
+    ///
+    /// The flags are 9 bits spread over bytes 12 and 13, with NS as the low
+    /// bit of byte 12 after the data offset and 3 reserved bits:
+    ///
+    /// ```text
+    ///   |            byte 12            |            byte 13            |
+    ///   +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+    ///   |  Data Offset  | Reserved  | NS|CWR|ECE|URG|ACK|PSH|RST|SYN|FIN|
+    ///   +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+    /// ```
+    ///
+    /// Panics if the checksum is stale after `flags_mut`, see
+    /// `recompute_checksum`.
+    ///
+    /// Also panics if the options don't fit in the header, see
+    /// `try_serialize`.
+    pub fn serialize(&self) -> Vec<u8> {
+        assert!(
+            !self.checksum_dirty,
+            "TCP checksum is stale after flags_mut, call recompute_checksum first"
+        );
+        self.header_bytes()
+    }
+
+    /// Like `serialize`, but returns an error for options that don't fit in
+    /// the header instead of panicking.
+    ///
+    /// # Errors
+    /// `EmptySack` or `TooManySackBlocks` for a SACK option without blocks
+    /// or with more than 4, and `OptionsTooLong` for options past 40 bytes,
+    /// where the data offset can't reach.
+    pub fn try_serialize(&self) -> Result<Vec<u8>, BuildError> {
+        options::check(&self.options)?;
+        Ok(self.serialize())
+    }
+
+    fn header_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.fixed_header().to_vec();
+        bytes.extend_from_slice(&self.options_bytes());
+        bytes
+    }
+
+    /// Serializes the fixed 20-byte header of a segment without options.
+    ///
+    /// Panics if there are options, which don't fit.
+    #[deprecated(note = "can't hold options, use `serialize`")]
     pub fn to_bytes(&self) -> [u8; 20] {
+        assert!(
+            self.options.is_empty(),
+            "to_bytes can't hold TCP options, use serialize"
+        );
+        self.fixed_header()
+    }
+
+    fn fixed_header(&self) -> [u8; 20] {
         let mut bytes = [0u8; 20];
         bytes[0..2].copy_from_slice(&self.source_port.to_be_bytes());
         bytes[2..4].copy_from_slice(&self.dest_port.to_be_bytes());
         bytes[4..8].copy_from_slice(&self.seq_num.to_be_bytes());
         bytes[8..12].copy_from_slice(&self.ack_num.to_be_bytes());
-        bytes[12] = (5 << 4) | 0; // Reserved = 0, data offset 5.
+        bytes[12] = (self.data_offset() << 4) | (self.reserved & 0x07) << 1 | self.ns as u8;
         bytes[13] = self.flags.bits();
         bytes[14..16].copy_from_slice(&self.window_size.to_be_bytes());
         bytes[16..18].copy_from_slice(&self.checksum.to_be_bytes());
-        bytes[18..20].copy_from_slice(&0u16.to_be_bytes());
+        bytes[18..20].copy_from_slice(&self.urgent_ptr.to_be_bytes());
         bytes
     }
 
+    /// Header length in 32-bit words: the fixed 5 words plus the padded
+    /// options.
+    ///
+    /// Panics if the options take more than 40 bytes, which 4 bits can't
+    /// count; `options::check` and `try_serialize` report it as an error.
+    pub fn data_offset(&self) -> u8 {
+        let options_len = options::padded_len(&self.options);
+        assert!(
+            options_len <= MAX_OPTIONS_LEN,
+            "TCP options take {} bytes, at most 40 fit",
+            options_len
+        );
+        ((20 + options_len) / 4) as u8
+    }
+
+    /// Header length in bytes, options included, i.e. where the payload
+    /// starts.
+    pub fn header_len(&self) -> usize {
+        self.data_offset() as usize * 4
+    }
+
+    fn options_bytes(&self) -> Vec<u8> {
+        options::to_bytes(&self.options)
+    }
+
+    /// Checksum over the IPv4 pseudo-header, see `calculate_checksum_with`.
+    fn calculate_checksum(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> u16 {
+        self.calculate_checksum_with(
+            PseudoHeader::V4 {
+                src: src_ip,
+                dst: dst_ip,
+            },
+            payload,
+        )
+    }
+
     /// Returns: 16-bit ones' complement of the ones' complement sum of all
-    /// 16-bit words in the header and text.
+    /// 16-bit words in the pseudo-header, header and text.
     ///
     /// The checksum computation needs to ensure the 16-bit alignment of the
     /// data being summed. If a segment contains an odd number of header and
     /// text octets, alignment can be achieved by padding the last octet with
     /// zeros on its right to form a 16-bit word for checksum purposes.
-    ///
-    /// The checksum also covers a pseudo-header conceptually prefixed to the
-    /// TCP header. The pseudo-header is 96 bits for IPv4 (12 bytes, 4 per row).
-    ///
-    ///   +--------+--------+--------+--------+
-    ///   |           Source Address          |
-    ///   +--------+--------+--------+--------+
-    ///   |         Destination Address       |
-    ///   +--------+--------+--------+--------+
-    ///   |  zero  |PTCL (6)|    TCP Length   |
-    ///   +--------+--------+--------+--------+
-    fn calculate_checksum(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> u16 {
+    pub fn calculate_checksum_with(&self, ph: PseudoHeader, payload: &[u8]) -> u16 {
+        let header = self.header_bytes();
+        let tcp_length = header.len() + payload.len();
+
         // The checksum itself is, according to the spec, is 16-bit long.
         // we use only the first two bytes of the u32 to do all summations.
-        let mut sum = 0u32;
+        let mut sum = ph.sum(PROTOCOL_TCP, tcp_length as u32);
+        // The header is always a whole number of words, so it can be summed
+        // apart from the payload.
+        sum += checksum::ones_complement_sum(&header) as u32;
+        sum += checksum::ones_complement_sum(payload) as u32;
+
+        !checksum::fold(sum)
+    }
+
+    /// Returns whether the stored checksum matches the pseudo-header, header
+    /// and `payload`.
+    ///
+    /// Summing a segment together with a correct checksum field yields all
+    /// ones, so recomputing the checksum over it must give zero.
+    pub fn verify_checksum(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> bool {
+        self.calculate_checksum(src_ip, dst_ip, payload) == 0
+    }
+
+    /// Whether the checksum field is 0 because 0 is the right checksum.
+    ///
+    /// A 0 field usually means the checksum was never computed, e.g. left
+    /// for the NIC to fill in, as in captures taken on the sending host.
+    /// But a segment whose words sum to `0xFFFF` folds to a checksum of 0,
+    /// and TCP, unlike UDP, writes that as is. Returns `true` only in that
+    /// second case, and `false` for a non-zero field whatever it holds.
+    pub fn checksum_is_valid_zero(
+        &self,
+        src_ip: Ipv4Addr,
+        dst_ip: Ipv4Addr,
+        payload: &[u8],
+    ) -> bool {
+        self.checksum == 0 && self.verify_checksum(src_ip, dst_ip, payload)
+    }
+
+    /// Like `verify_checksum`, for any pseudo-header.
+    pub fn verify_checksum_with(&self, ph: PseudoHeader, payload: &[u8]) -> bool {
+        self.calculate_checksum_with(ph, payload) == 0
+    }
 
-        // Pseudo-header: src IP (4 bytes)
-        sum += u16::from_be_bytes(src_ip.octets()[0..2].try_into().unwrap()) as u32;
-        sum += u16::from_be_bytes(src_ip.octets()[2..4].try_into().unwrap()) as u32;
+    /// Patches the checksum for a 16-bit word of the segment (or of its
+    /// pseudo-header) changing from `old` to `new`, per RFC 1624, without
+    /// summing the payload again.
+    pub fn update_checksum_field(&mut self, old: u16, new: u16) {
+        self.checksum = checksum::update(self.checksum, old, new);
+    }
+
+    /// The flags, for changing in place. Marks the checksum as stale, so
+    /// `serialize` and `build_packet` panic until `recompute_checksum` is
+    /// called. Assigning to `flags` directly isn't tracked.
+    pub fn flags_mut(&mut self) -> &mut TcpFlags {
+        self.checksum_dirty = true;
+        &mut self.flags
+    }
+
+    /// Whether the checksum went stale through `flags_mut`.
+    pub fn checksum_dirty(&self) -> bool {
+        self.checksum_dirty
+    }
+
+    /// Recomputes the checksum over the IPv4 pseudo-header, header and
+    /// `payload`, and clears `checksum_dirty`.
+    pub fn recompute_checksum(&mut self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) {
+        self.checksum = 0;
+        self.checksum = self.calculate_checksum(src_ip, dst_ip, payload);
+        self.checksum_dirty = false;
+    }
+
+    /// Compares every field but the checksum, for a header that was rebuilt
+    /// or whose checksum hasn't been filled in yet. `==` compares the
+    /// checksum too.
+    pub fn equivalent_ignoring_checksum(&self, other: &Tcp) -> bool {
+        let Tcp {
+            source_port,
+            dest_port,
+            seq_num,
+            ack_num,
+            flags,
+            ns,
+            reserved,
+            window_size,
+            checksum: _,
+            urgent_ptr,
+            options,
+            checksum_dirty: _,
+        } = self;
 
-        // Pseudo-header: dst IP (4 bytes)
-        sum += u16::from_be_bytes(dst_ip.octets()[0..2].try_into().unwrap()) as u32;
-        sum += u16::from_be_bytes(dst_ip.octets()[2..4].try_into().unwrap()) as u32;
+        *source_port == other.source_port
+            && *dest_port == other.dest_port
+            && *seq_num == other.seq_num
+            && *ack_num == other.ack_num
+            && *flags == other.flags
+            && *ns == other.ns
+            && *reserved == other.reserved
+            && *window_size == other.window_size
+            && *urgent_ptr == other.urgent_ptr
+            && *options == other.options
+    }
+
+    /// Length in sequence space of this segment carrying `payload_len`
+    /// bytes: SYN and FIN each take up a sequence number of their own.
+    pub fn segment_len(&self, payload_len: usize) -> u32 {
+        payload_len as u32
+            + self.flags.contains(TcpFlags::SYN) as u32
+            + self.flags.contains(TcpFlags::FIN) as u32
+    }
 
-        // Pseudo-header: Reserved (0), Protocol number: (6), TCP Length
-        sum += 0x06_u32; // Protocol = 6 for TCP
-        let tcp_length = (self.to_bytes().len() + payload.len()) as u16;
-        sum += tcp_length as u32;
+    /// Whether the sender's receive buffer is full: a window of 0 on
+    /// anything but a RST, whose window means nothing.
+    pub fn advertises_zero_window(&self) -> bool {
+        self.window_size == 0 && !self.flags.contains(TcpFlags::RST)
+    }
 
-        // TCP headers
-        for chunk in self.to_bytes().chunks(2) {
-            sum += u16::from_be_bytes(chunk.try_into().unwrap()) as u32;
+    /// The urgent bytes at the start of `payload` when URG is set.
+    ///
+    /// The urgent pointer is an offset from `seq_num` to the byte *after*
+    /// the urgent data (RFC 793, which RFC 1122 changed to the last urgent
+    /// byte and RFC 6093 changed back, as every stack does), so a pointer of
+    /// 3 covers `payload[..3]`. A pointer past the payload means the urgent
+    /// data goes on into later segments, and all of `payload` is urgent. A
+    /// pointer of 0 marks nothing and gives `None`.
+    pub fn urgent_data<'a>(&self, payload: &'a [u8]) -> Option<&'a [u8]> {
+        if !self.flags.contains(TcpFlags::URG) || self.urgent_ptr == 0 {
+            return None;
         }
+        Some(&payload[..payload.len().min(self.urgent_ptr as usize)])
+    }
 
-        // Payload
-        for chunk in payload.chunks(2) {
-            if chunk.len() == 2 {
-                sum += u16::from_be_bytes(chunk.try_into().unwrap()) as u32;
-            } else {
-                sum += (chunk[0] as u16) as u32;
-            }
+    /// Whether this is a connection request: SYN set and ACK clear. The
+    /// answer to one, with both set, is `is_syn_ack` instead, so a SYN-ACK
+    /// is never `is_syn`.
+    pub fn is_syn(&self) -> bool {
+        self.flags.contains(TcpFlags::SYN) && !self.flags.contains(TcpFlags::ACK)
+    }
+
+    /// Whether both SYN and ACK are set, as in the second step of the
+    /// handshake.
+    pub fn is_syn_ack(&self) -> bool {
+        self.flags.contains(TcpFlags::SYN_ACK)
+    }
+
+    /// Whether ACK is set, whatever else is. A SYN-ACK is `is_ack` too.
+    pub fn is_ack(&self) -> bool {
+        self.flags.contains(TcpFlags::ACK)
+    }
+
+    pub fn is_fin(&self) -> bool {
+        self.flags.contains(TcpFlags::FIN)
+    }
+
+    pub fn is_rst(&self) -> bool {
+        self.flags.contains(TcpFlags::RST)
+    }
+
+    pub fn is_psh(&self) -> bool {
+        self.flags.contains(TcpFlags::PSH)
+    }
+
+    /// Whether this looks like a keepalive probe (RFC 1122 4.2.3.6) rather
+    /// than data, `rcv_nxt` being the next sequence number expected from the
+    /// peer: a bare ACK one before it, for a byte already received, with
+    /// `payload_len` of 0 or 1.
+    ///
+    /// RFC 1122 leaves the sequence number open, and stacks use one of two:
+    ///
+    /// - `rcv_nxt - 1`, for a byte the peer already has. Linux, like
+    ///   `Stream::keepalive_probe`, sends no payload; Windows and older BSDs
+    ///   send that one byte again, which must not be taken as new data.
+    ///   These are the probes reported.
+    /// - `rcv_nxt` itself, with no payload. Such a probe is byte for byte a
+    ///   duplicate ACK or a window update, so nothing in the segment marks
+    ///   it as a probe and it is never reported. Only the timing (a bare ACK
+    ///   after an idle period) gives it away.
+    ///
+    /// Either way the answer is an ACK of `rcv_nxt`.
+    pub fn is_keepalive(&self, rcv_nxt: u32, payload_len: usize) -> bool {
+        self.flags & !(TcpFlags::PSH | TcpFlags::ECE | TcpFlags::CWR) == TcpFlags::ACK
+            && self.seq_num == rcv_nxt.wrapping_sub(1)
+            && payload_len <= 1
+    }
+
+    /// Whether this is an ECN-setup SYN (RFC 3168): SYN with both ECE and
+    /// CWR, offering ECN.
+    pub fn is_ecn_setup_syn(&self) -> bool {
+        self.flags
+            .contains(TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR)
+            && !self.flags.contains(TcpFlags::ACK)
+    }
+
+    /// Whether this is an ECN-setup SYN-ACK (RFC 3168): SYN-ACK with ECE but
+    /// not CWR, accepting ECN. A SYN-ACK with both set is a reflected SYN
+    /// from a broken middlebox and doesn't count.
+    pub fn is_ecn_setup_syn_ack(&self) -> bool {
+        self.flags.contains(TcpFlags::SYN_ACK | TcpFlags::ECE)
+            && !self.flags.contains(TcpFlags::CWR)
+    }
+
+    /// Sets the source port, updating the checksum incrementally.
+    pub fn set_source_port(&mut self, port: u16) {
+        self.update_checksum_field(self.source_port, port);
+        self.source_port = port;
+    }
+
+    /// Sets the destination port, updating the checksum incrementally.
+    pub fn set_dest_port(&mut self, port: u16) {
+        self.update_checksum_field(self.dest_port, port);
+        self.dest_port = port;
+    }
+
+    /// The receive window in bytes: `window_size` shifted left by the
+    /// window scale option when the segment carries one. Shifts above 14
+    /// are treated as 14, as RFC 7323 tells receivers to do.
+    pub fn effective_window(&self) -> u32 {
+        self.scaled_window(self.window_scale().unwrap_or(0))
+    }
+
+    /// The shift count of the window scale option, if the segment has one.
+    /// It's only sent on SYNs and applies to the windows the sender
+    /// advertises after them, once both sides have sent it.
+    pub fn window_scale(&self) -> Option<u8> {
+        self.options.iter().find_map(|option| match option {
+            TcpOption::WindowScale(shift) => Some(*shift),
+            _ => None,
+        })
+    }
+
+    /// `window_size` shifted left by `shift`, a window scale negotiated on
+    /// the SYNs, capped at 14 like `effective_window`.
+    pub fn scaled_window(&self, shift: u8) -> u32 {
+        (self.window_size as u32) << shift.min(MAX_WINDOW_SCALE)
+    }
+
+    /// The reset to answer this segment with when it doesn't belong to any
+    /// connection, following RFC 793's reset generation rules:
+    ///
+    /// - if it had ACK set, the RST takes its sequence number from the
+    ///   segment's ack number: `<SEQ=SEG.ACK><CTL=RST>`
+    /// - otherwise it acknowledges everything the segment occupied:
+    ///   `<SEQ=0><ACK=SEG.SEQ+SEG.LEN><CTL=RST,ACK>`, where SYN and FIN
+    ///   each count as one in `SEG.LEN` on top of `payload_len`
+    ///
+    /// `src_ip` and `dst_ip` are the addresses this segment travelled
+    /// between; the RST goes the other way. A RST is never answered, so
+    /// that gives `None`.
+    pub fn rst_for(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload_len: usize) -> Option<Tcp> {
+        if self.flags.contains(TcpFlags::RST) {
+            return None;
         }
 
-        // Fold 32-bit sum into 16-bit.
-        //
-        // If the addition of the high and low 16 bits produces any carry-out,
-        // (i.e. the new sum exceeds 16 bits) the process is repetead till no
-        // carry-out.
-        // perseving the mathematical correctness of one's complement by
-        // adding any carry-out back the the lower 16 bits.
-        while (sum >> 16) > 0 {
-            // `sum && 0xFFF` extract the low 16 bits of sum.
-            // `sum >> 16` extracts the high 16 bits of sum,
-            sum = (sum & 0xFFFF) + (sum >> 16);
+        let mut rst = TcpBuilder::new();
+        rst.source_port(self.dest_port)
+            .dest_port(self.source_port)
+            .window_size(0);
+
+        if self.flags.contains(TcpFlags::ACK) {
+            rst.seq_num(self.ack_num).flags(TcpFlags::RST);
+        } else {
+            rst.seq_num(0)
+                .ack_num(self.seq_num.wrapping_add(self.segment_len(payload_len)))
+                .flags(TcpFlags::RST_ACK);
         }
 
-        !(sum as u16)
+        Some(rst.build(dst_ip, src_ip, b""))
+    }
+
+    /// Pairs this header with the `payload` it carries, e.g. to pass the
+    /// whole segment around.
+    pub fn with_payload(self, payload: &[u8]) -> Segment {
+        Segment {
+            header: self,
+            payload: payload.to_vec(),
+        }
     }
 
     pub fn build_packet(&self, payload: &[u8]) -> Vec<u8> {
-        let mut packet = Vec::new();
-        packet.extend_from_slice(&self.to_bytes());
+        let mut packet = self.serialize();
         packet.extend_from_slice(payload);
 
         packet
     }
 
-    pub fn parse_packet(bytes: &[u8]) -> (Tcp, Option<String>) {
-        let tcp = Tcp::try_from(bytes).unwrap();
-        let payload = if bytes.len() > 20 {
-            Some(String::from_utf8_lossy(&bytes[20..]).into_owned())
-        } else {
+    /// Parses a segment into its header and the payload that follows the
+    /// header and its options, decoded lossily as UTF-8.
+    ///
+    /// Use `parse_packet_raw` for binary payloads, and `TcpParser` to change
+    /// the `MAX_SEGMENT_SIZE` limit.
+    pub fn parse_packet(bytes: &[u8]) -> Result<(Tcp, Option<String>), TcpParseError> {
+        TcpParser::new().parse_packet(bytes)
+    }
+
+    /// Parses a segment into its header and the raw payload, borrowed from
+    /// `bytes` starting at the data offset.
+    pub fn parse_packet_raw(bytes: &[u8]) -> Result<(Tcp, &[u8]), TcpParseError> {
+        TcpParser::new().parse_packet_raw(bytes)
+    }
+
+    /// Parses a segment as `parse_packet_raw` does, also checking its
+    /// checksum against `src_ip` and `dst_ip`, which come from the IP header
+    /// the segment arrived in. A bad checksum isn't an error: the segment is
+    /// still returned, with `false`, for the caller to count or drop.
+    pub fn parse_and_verify(
+        bytes: &[u8],
+        src_ip: Ipv4Addr,
+        dst_ip: Ipv4Addr,
+    ) -> Result<(Tcp, Vec<u8>, bool), TcpParseError> {
+        let (tcp, payload) = Tcp::parse_packet_raw(bytes)?;
+        let checksum_ok = tcp.verify_checksum(src_ip, dst_ip, payload);
+        Ok((tcp, payload.to_vec(), checksum_ok))
+    }
+
+    /// Parses a segment written out as hex, as copied from Wireshark, into
+    /// its header and payload. Whitespace and colons are ignored.
+    ///
+    /// ```
+    /// use harbinger::tcp::Tcp;
+    /// let (tcp, payload) = Tcp::from_hex(
+    ///     "c0a8 1f90 1234 5678 8765 4321 5018 00ff f00d 0000 6869",
+    /// ).unwrap();
+    /// assert_eq!(tcp.dest_port, 8080);
+    /// assert_eq!(payload, b"hi");
+    /// ```
+    pub fn from_hex(s: &str) -> Result<(Tcp, Vec<u8>), ParseError> {
+        let bytes = decode_hex(s)?;
+        let (tcp, payload) = Tcp::parse_packet_raw(&bytes)?;
+        Ok((tcp, payload.to_vec()))
+    }
+
+    /// The segment, header and `payload`, as a hex string `from_hex` reads
+    /// back.
+    pub fn to_hex(&self, payload: &[u8]) -> String {
+        encode_hex(&self.build_packet(payload))
+    }
+}
+
+/// Settings for parsing whole segments, for callers that need a limit other
+/// than `MAX_SEGMENT_SIZE`.
+///
+/// ```
+/// use harbinger::tcp::TcpParser;
+/// let segment = [0x00, 0x50, 0x01, 0xBB, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0x04, 0x00,
+///                0, 0, 0, 0, b'h', b'i'];
+/// let mut parser = TcpParser::new();
+/// parser.max_segment_size(20);
+/// assert!(parser.parse_packet_raw(&segment).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TcpParser {
+    max_segment_size: usize,
+}
+
+impl Default for TcpParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TcpParser {
+    pub fn new() -> Self {
+        Self {
+            max_segment_size: MAX_SEGMENT_SIZE,
+        }
+    }
+
+    /// Longest segment, header included, to accept before looking at it.
+    pub fn max_segment_size(&mut self, max: usize) -> &mut Self {
+        self.max_segment_size = max;
+        self
+    }
+
+    /// See `Tcp::parse_packet`.
+    pub fn parse_packet(&self, bytes: &[u8]) -> Result<(Tcp, Option<String>), TcpParseError> {
+        let (tcp, payload) = self.parse_packet_raw(bytes)?;
+        let payload = if payload.is_empty() {
             None
+        } else {
+            Some(String::from_utf8_lossy(payload).into_owned())
         };
 
-        (tcp, payload)
+        Ok((tcp, payload))
     }
+
+    /// See `Tcp::parse_packet_raw`.
+    ///
+    /// # Errors
+    /// `TooLong` if `bytes` is longer than the limit, otherwise as
+    /// `Tcp::try_from`.
+    pub fn parse_packet_raw<'a>(&self, bytes: &'a [u8]) -> Result<(Tcp, &'a [u8]), TcpParseError> {
+        if bytes.len() > self.max_segment_size {
+            return Err(TcpParseError::TooLong {
+                got: bytes.len(),
+                max: self.max_segment_size,
+            });
+        }
+
+        let tcp = Tcp::try_from(bytes)?;
+        // `try_from` has checked the data offset against `bytes`.
+        let header_len = wire_data_offset(bytes) as usize * 4;
+        // Options after an EOL are dropped, so the parsed header can be
+        // shorter than the one on the wire, never longer.
+        debug_assert!(
+            tcp.header_len() <= header_len,
+            "parsed header outgrew the data offset it was read with"
+        );
+
+        Ok((tcp, &bytes[header_len..]))
+    }
+}
+
+/// The data offset of a raw header, in 32-bit words: the high nibble of
+/// byte 12, whose low nibble holds the reserved bits and NS. Everything
+/// reading a header off the wire gets it from here, so the two can't be
+/// mixed up in one place and not another. `bytes` must be at least 13 bytes.
+fn wire_data_offset(bytes: &[u8]) -> u8 {
+    bytes[12] >> 4
 }
 
 /// Converts a slice of bytes into a `Tcp` instance.
@@ -136,12 +703,18 @@ impl Tcp {
 /// # Parameters
 /// - `bytes`: A slice of bytes representing a TCP header. Must be at least 20 bytes long.
 ///
-/// # Panics
-/// This function will panic if the provided `bytes` slice is less than 20 bytes long.
+/// # Errors
+/// - `TooShort` if the provided `bytes` slice is less than 20 bytes long.
+/// - `BadDataOffset` if the data offset is below 5 words or claims more bytes
+///   than `bytes` holds.
+/// - `BadOption` if an option's length byte is missing or can't be right.
 ///
 /// # Notes
 /// - The function assumes the input byte slice follows the TCP header structure.
-/// - The `flags` field is parsed into a `TcpFlags` instance, ensuring valid flag combinations.
+/// - Every flags byte is accepted and kept bit for bit, so any combination,
+///   even an illegal one (see `TcpFlags::validate`), parses and serializes
+///   back unchanged.
+/// - Options between byte 20 and the data offset are decoded into `options`.
 ///
 /// # Example
 /// ```
@@ -151,18 +724,21 @@ impl Tcp {
 ///     0x87, 0x65, 0x43, 0x21, 0x50, 0x18, 0x00, 0xFF,
 ///     0xF0, 0x0D, 0x00, 0x00,
 /// ];
-/// let tcp = Tcp::try_from(&raw_bytes[..]);
+/// let tcp = Tcp::try_from(&raw_bytes[..]).unwrap();
 /// println!("{:?}", tcp);
 /// ```
 impl TryFrom<&[u8]> for Tcp {
-    type Error = &'static str;
+    type Error = TcpParseError;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         if bytes.len() < 20 {
-            panic!(
-                "TCP Header must be at least 20 bytes, received: {}",
-                bytes.len()
-            );
+            return Err(TcpParseError::TooShort { got: bytes.len() });
+        }
+
+        let data_offset = wire_data_offset(bytes);
+        let header_len = data_offset as usize * 4;
+        if data_offset < 5 || header_len > bytes.len() {
+            return Err(TcpParseError::BadDataOffset(data_offset));
         }
 
         Ok(Self {
@@ -170,20 +746,98 @@ impl TryFrom<&[u8]> for Tcp {
             dest_port: u16::from_be_bytes(bytes[2..4].try_into().unwrap()),
             seq_num: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
             ack_num: u32::from_be_bytes(bytes[8..12].try_into().unwrap()),
-            flags: TcpFlags::from_bits(bytes[13]).unwrap(),
+            flags: TcpFlags::from_bits_retain(bytes[13]),
+            ns: bytes[12] & 0x01 != 0,
+            reserved: (bytes[12] >> 1) & 0x07,
             window_size: u16::from_be_bytes(bytes[14..16].try_into().unwrap()),
             checksum: u16::from_be_bytes(bytes[16..18].try_into().unwrap()),
+            urgent_ptr: u16::from_be_bytes(bytes[18..20].try_into().unwrap()),
+            options: options::parse(&bytes[20..header_len])?,
+            checksum_dirty: false,
         })
     }
 }
 
+/// Headers that serialize to what `try_from` reads back: reserved bits
+/// within their 3 bits, and options that fit in `MAX_OPTIONS_LEN`, so the
+/// data offset is always valid. The checksum is arbitrary too, not
+/// computed.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Tcp {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut tcp = Tcp {
+            source_port: u.arbitrary()?,
+            dest_port: u.arbitrary()?,
+            seq_num: u.arbitrary()?,
+            ack_num: u.arbitrary()?,
+            flags: u.arbitrary()?,
+            ns: u.arbitrary()?,
+            reserved: u.int_in_range(0..=7)?,
+            window_size: u.arbitrary()?,
+            checksum: u.arbitrary()?,
+            urgent_ptr: u.arbitrary()?,
+            options: Vec::new(),
+            checksum_dirty: false,
+        };
+
+        let mut options_len = 0;
+        while u.arbitrary()? {
+            let option: TcpOption = u.arbitrary()?;
+            options_len += option.encoded_len();
+            if options_len > MAX_OPTIONS_LEN {
+                break;
+            }
+            tcp.options.push(option);
+        }
+        Ok(tcp)
+    }
+}
+
+/// Parses the header at the start of `bytes` without copying them, as
+/// `TryFrom<&[u8]>` does.
+impl TryFrom<Vec<u8>> for Tcp {
+    type Error = TcpParseError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        Tcp::try_from(&bytes[..])
+    }
+}
+
+/// The header with its options, as from `serialize`.
+impl From<&Tcp> for Vec<u8> {
+    fn from(tcp: &Tcp) -> Self {
+        tcp.serialize()
+    }
+}
+
+impl From<Tcp> for Vec<u8> {
+    fn from(tcp: Tcp) -> Self {
+        tcp.serialize()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TcpBuilder {
     source_port: u16,
     dest_port: u16,
     seq_num: u32,
     ack_num: u32,
     flags: TcpFlags,
+    ns: bool,
+    reserved: u8,
     window_size: u16,
+    urgent_ptr: u16,
+    options: Vec<TcpOption>,
+    ephemeral_source_only: bool,
+    ecn_setup: bool,
+    no_checksum: bool,
+    payload: Option<Vec<u8>>,
+}
+
+impl Default for TcpBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TcpBuilder {
@@ -194,15 +848,47 @@ impl TcpBuilder {
             seq_num: 0,
             ack_num: 0,
             flags: TcpFlags::UNINT,
+            ns: false,
+            reserved: 0,
             window_size: 1024, // Default
+            urgent_ptr: 0,
+            options: Vec::new(),
+            ephemeral_source_only: false,
+            ecn_setup: false,
+            no_checksum: false,
+            payload: None,
         }
     }
 
+    /// Port 0 is reserved and almost never valid: no stack will answer it,
+    /// and `warnings` reports it. See `ephemeral_source_port` to
+    /// pick one.
     pub fn source_port(&mut self, port: u16) -> &mut Self {
         self.source_port = port;
         self
     }
 
+    /// Picks a random source port in the ephemeral range. The chosen port is
+    /// the `source_port` of the built `Tcp`.
+    #[cfg(feature = "std")]
+    pub fn ephemeral_source_port(&mut self) -> &mut Self {
+        use std::hash::{BuildHasher, Hasher};
+
+        // `RandomState` is seeded randomly per process.
+        let seed = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        self.ephemeral_source_port_seeded(seed)
+    }
+
+    /// Like `ephemeral_source_port`, but the same `seed` always picks the
+    /// same port.
+    pub fn ephemeral_source_port_seeded(&mut self, seed: u64) -> &mut Self {
+        let span = (u16::MAX - EPHEMERAL_PORT_START) as u64 + 1;
+        self.source_port = EPHEMERAL_PORT_START + (splitmix64(seed) % span) as u16;
+        self
+    }
+
     pub fn dest_port(&mut self, port: u16) -> &mut Self {
         self.dest_port = port;
         self
@@ -223,44 +909,309 @@ impl TcpBuilder {
         self
     }
 
+    pub fn ns(&mut self, ns: bool) -> &mut Self {
+        self.ns = ns;
+        self
+    }
+
+    /// Sets the reserved bits (the low 3 of `bits`), for conformance tests.
+    /// `build_checked` refuses anything but 0.
+    pub fn reserved(&mut self, bits: u8) -> &mut Self {
+        self.reserved = bits & 0x07;
+        self
+    }
+
     pub fn window_size(&mut self, size: u16) -> &mut Self {
         self.window_size = size;
         self
     }
 
+    pub fn urgent_ptr(&mut self, ptr: u16) -> &mut Self {
+        self.urgent_ptr = ptr;
+        self
+    }
+
+    /// Advertises the largest segment we are willing to receive, as real
+    /// stacks do on SYN.
+    pub fn mss(&mut self, mss: u16) -> &mut Self {
+        self.options.push(TcpOption::Mss(mss));
+        self
+    }
+
+    /// Advertises a window scale shift count (RFC 7323). Only meaningful on
+    /// SYN segments.
+    pub fn window_scale(&mut self, shift: u8) -> &mut Self {
+        self.options.push(TcpOption::WindowScale(shift));
+        self
+    }
+
+    /// Tells the peer we understand SACK blocks (RFC 2018). Only
+    /// meaningful on SYN segments.
+    pub fn sack_permitted(&mut self) -> &mut Self {
+        self.options.push(TcpOption::SackPermitted);
+        self
+    }
+
+    /// Reports received out-of-order ranges as `(left, right)` edges. At
+    /// least one and at most `options::MAX_SACK_BLOCKS` fit: `build_checked`
+    /// rejects none or more, and `build` panics on more rather than write a
+    /// wrong header.
+    pub fn sack(&mut self, blocks: &[(u32, u32)]) -> &mut Self {
+        self.options.push(TcpOption::Sack(blocks.to_vec()));
+        self
+    }
+
+    /// Adds the timestamps option (RFC 7323), preceded by two NOPs so it
+    /// stays word-aligned the way real stacks lay it out.
+    pub fn timestamp(&mut self, tsval: u32, tsecr: u32) -> &mut Self {
+        self.options.push(TcpOption::Nop);
+        self.options.push(TcpOption::Nop);
+        self.options.push(TcpOption::Timestamp { tsval, tsecr });
+        self
+    }
+
+    /// Makes `build_checked` reject source ports outside the IANA ephemeral
+    /// range (49152-65535). Off by default.
+    pub fn ephemeral_source_only(&mut self, on: bool) -> &mut Self {
+        self.ephemeral_source_only = on;
+        self
+    }
+
+    /// Offers (on a SYN) or accepts (on a SYN-ACK) ECN, RFC 3168 section
+    /// 6.1.1: a SYN gets ECE and CWR, a SYN-ACK only ECE. Segments without
+    /// SYN are left alone.
+    pub fn ecn_setup(&mut self) -> &mut Self {
+        self.ecn_setup = true;
+        self
+    }
+
+    /// Leaves the checksum at 0 instead of computing it, for sockets whose
+    /// kernel or NIC fills it in on the way out (checksum offload). Without
+    /// offload the peer drops such segments, and `verify_checksum` reports
+    /// them as bad.
+    pub fn no_checksum(&mut self) -> &mut Self {
+        self.no_checksum = true;
+        self
+    }
+
+    /// Stores the payload, so `build_packet` can checksum and append the
+    /// same bytes. `build` and `build_with` still take theirs as an
+    /// argument, and debug builds panic if it isn't this one.
+    pub fn payload(&mut self, data: &[u8]) -> &mut Self {
+        self.payload = Some(data.to_vec());
+        self
+    }
+
+    /// Builds the segment over the payload set with `payload` (empty if
+    /// none was) and serializes it with that payload appended, so the
+    /// checksum can't cover different bytes than the ones sent.
+    pub fn build_packet(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> Vec<u8> {
+        let payload = self.payload.as_deref().unwrap_or_default();
+        self.build(src_ip, dst_ip, payload).build_packet(payload)
+    }
+
     pub fn build(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> Tcp {
+        self.build_with(
+            PseudoHeader::V4 {
+                src: src_ip,
+                dst: dst_ip,
+            },
+            payload,
+        )
+    }
+
+    /// Like `build`, taking the source address from the route to `dst_ip`
+    /// (see `raw::source_ip_for`). The checksum covers both addresses, so
+    /// it's only right if `src_ip` is the one the kernel actually sends
+    /// from, which a socket bound to `0.0.0.0` only picks at send time.
+    #[cfg(feature = "std")]
+    pub fn build_for_dest(&self, dst_ip: Ipv4Addr, payload: &[u8]) -> std::io::Result<Tcp> {
+        let src_ip = crate::raw::source_ip_for(dst_ip)?;
+        Ok(self.build(src_ip, dst_ip, payload))
+    }
+
+    /// Like `build`, checksumming against any pseudo-header (e.g. IPv6).
+    pub fn build_with(&self, ph: PseudoHeader, payload: &[u8]) -> Tcp {
+        debug_assert!(
+            self.payload
+                .as_deref()
+                .is_none_or(|stored| stored == payload),
+            "payload passed to build differs from the one set with TcpBuilder::payload"
+        );
+
+        let mut flags = self.flags;
+        if self.ecn_setup && flags.contains(TcpFlags::SYN) {
+            if flags.contains(TcpFlags::ACK) {
+                flags.insert(TcpFlags::ECE);
+                flags.remove(TcpFlags::CWR);
+            } else {
+                flags.insert(TcpFlags::ECE | TcpFlags::CWR);
+            }
+        }
+
         let mut tcp = Tcp {
             source_port: self.source_port,
             dest_port: self.dest_port,
             seq_num: self.seq_num,
             ack_num: self.ack_num,
-            flags: self.flags,
+            flags,
+            ns: self.ns,
+            reserved: self.reserved,
             checksum: 0,
             window_size: self.window_size,
+            urgent_ptr: self.urgent_ptr,
+            options: self.options.clone(),
+            checksum_dirty: false,
         };
 
         // Calculate checksum for the whole tcp packet.
-        let checksum = tcp.calculate_checksum(src_ip, dst_ip, payload);
-        tcp.checksum = checksum;
+        if !self.no_checksum {
+            tcp.checksum = tcp.calculate_checksum_with(ph, payload);
+        }
 
         tcp
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Like `build`, but refuses segments no sane stack would send. See
+    /// `BuildError` for what gets rejected.
+    pub fn build_checked(
+        &self,
+        src_ip: Ipv4Addr,
+        dst_ip: Ipv4Addr,
+        payload: &[u8],
+    ) -> Result<Tcp, BuildError> {
+        self.validate()?;
+        Ok(self.build(src_ip, dst_ip, payload))
+    }
 
-    use super::*;
+    fn validate(&self) -> Result<(), BuildError> {
+        self.flags.validate()?;
+        if self.flags.contains(TcpFlags::ACK) && self.ack_num == 0 {
+            return Err(BuildError::ZeroAckNum);
+        }
+        if self.reserved != 0 {
+            return Err(BuildError::NonZeroReserved(self.reserved));
+        }
+        for option in &self.options {
+            if let TcpOption::WindowScale(shift) = option {
+                if *shift > MAX_WINDOW_SCALE {
+                    return Err(BuildError::WindowScaleTooLarge(*shift));
+                }
+            }
+        }
+        options::check(&self.options)?;
+        if self.ephemeral_source_only && self.source_port < EPHEMERAL_PORT_START {
+            return Err(BuildError::NonEphemeralSourcePort(self.source_port));
+        }
+        Ok(())
+    }
 
-    fn get_tcp() -> Tcp {
-        Tcp {
-            source_port: 49320,
+    /// Setups `build_checked` accepts but that are almost always mistakes,
+    /// for the caller to report. Port 0 is legal on the wire, and tests
+    /// send it on purpose, so it is only warned about.
+    pub fn warnings(&self) -> Vec<BuildWarning> {
+        let mut warnings = Vec::new();
+        if self.source_port == 0 {
+            warnings.push(BuildWarning::ZeroSourcePort);
+        }
+        warnings
+    }
+}
+
+/// `count` segments from `builder`, each carrying `payload`, for load
+/// testing. The first has `base_seq` as its sequence number and each next
+/// one follows on by `payload.len()`, wrapping at 2^32. Every segment is
+/// checksummed for its own sequence number between `src_ip` and `dst_ip`.
+/// A payload stored with `TcpBuilder::payload` is ignored.
+pub fn packet_stream<'a>(
+    src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+    base_seq: u32,
+    count: u32,
+    payload: &'a [u8],
+    builder: &TcpBuilder,
+) -> impl Iterator<Item = Vec<u8>> + 'a {
+    let mut builder = builder.clone();
+    builder.payload = None;
+    let len = payload.len() as u32;
+    (0..count).map(move |i| {
+        builder
+            .seq_num(base_seq.wrapping_add(i.wrapping_mul(len)))
+            .build(src_ip, dst_ip, payload)
+            .build_packet(payload)
+    })
+}
+
+/// Cuts `payload` into segments of at most `mss` bytes from `builder`, the
+/// first at `base_seq` and each next one right after the last, wrapping at
+/// 2^32. Each is checksummed between `src_ip` and `dst_ip` and returned as
+/// header and payload, ready for the wire. PSH is set on the last segment
+/// only. An empty payload gives no segments. A payload stored with
+/// `TcpBuilder::payload` is ignored.
+///
+/// Panics if `mss` is 0.
+pub fn segment(
+    payload: &[u8],
+    mss: u16,
+    base_seq: u32,
+    builder: &TcpBuilder,
+    src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+) -> Vec<Vec<u8>> {
+    assert!(mss > 0, "MSS must be at least 1 byte");
+    let mut builder = builder.clone();
+    builder.payload = None;
+    let base_flags = builder.flags;
+    let chunks = payload.chunks(mss as usize);
+    let last = chunks.len().saturating_sub(1);
+
+    let mut seq = base_seq;
+    chunks
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut flags = base_flags;
+            flags.set(TcpFlags::PSH, i == last);
+            let packet = builder
+                .seq_num(seq)
+                .flags(flags)
+                .build(src_ip, dst_ip, chunk)
+                .build_packet(chunk);
+            seq = seq.wrapping_add(chunk.len() as u32);
+            packet
+        })
+        .collect()
+}
+
+/// One step of SplitMix64, enough to spread a seed over the port range.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::FlagError;
+    use crate::options::MAX_SACK_BLOCKS;
+    use proptest::prelude::*;
+
+    fn get_tcp() -> Tcp {
+        Tcp {
+            source_port: 49320,
             dest_port: 8080,
             seq_num: 305419896,
             ack_num: 2271560481,
             flags: TcpFlags::SYN | TcpFlags::ACK,
+            ns: false,
+            reserved: 0,
             window_size: 255,
             checksum: 61453,
+            urgent_ptr: 0,
+            options: Vec::new(),
+            checksum_dirty: false,
         }
     }
 
@@ -290,6 +1241,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_tcp_headers_to_bytes() {
         let raw_bytes = get_tcp().to_bytes();
 
@@ -309,6 +1261,147 @@ mod tests {
         )
     }
 
+    /// Every multi-byte field is big-endian on the wire, whatever the
+    /// host. The values are lopsided so reading any of them in little-endian
+    /// order gives a different number.
+    #[test]
+    fn test_tcp_wire_byte_order() {
+        let bytes: [u8; 36] = [
+            0x01, 0xBB, // Source Port: 443 (little-endian would be 47873)
+            0x1F, 0x90, // Destination Port: 8080
+            0x01, 0x02, 0x03, 0x04, // Sequence Number: 16909060
+            0xF0, 0x00, 0x00, 0x01, // Acknowledgment Number: 4026531841
+            0x93, // Data Offset: 9 words, Reserved: 0b001, NS: 1
+            0x38, // Flags: URG | ACK | PSH
+            0x01, 0x02, // Window Size: 258
+            0xAB, 0xCD, // Checksum: 43981
+            0x00, 0x10, // Urgent Pointer: 16
+            0x02, 0x04, 0x05, 0xB4, // MSS: 1460
+            0x08, 0x0A, 0x00, 0x00, 0x01, 0x00, // Timestamp value: 256
+            0x7F, 0xFF, 0xFF, 0xFE, // Timestamp echo reply: 2147483646
+            0x01, 0x00, // NOP, EOL
+        ];
+        let tcp = Tcp::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(tcp.source_port, 443);
+        assert_eq!(tcp.dest_port, 8080);
+        assert_eq!(tcp.seq_num, 16909060);
+        assert_eq!(tcp.ack_num, 4026531841);
+        assert_eq!(tcp.data_offset(), 9);
+        assert_eq!(tcp.reserved, 1);
+        assert!(tcp.ns);
+        assert_eq!(tcp.flags, TcpFlags::URG | TcpFlags::ACK | TcpFlags::PSH);
+        assert_eq!(tcp.window_size, 258);
+        assert_eq!(tcp.checksum, 43981);
+        assert_eq!(tcp.urgent_ptr, 16);
+        assert_eq!(
+            tcp.options,
+            [
+                TcpOption::Mss(1460),
+                TcpOption::Timestamp {
+                    tsval: 256,
+                    tsecr: 2147483646
+                },
+                TcpOption::Nop,
+            ]
+        );
+        assert_eq!(tcp.serialize(), bytes);
+    }
+
+    #[test]
+    fn test_tcp_eq_round_trip() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dst_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let tcp = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .mss(1460)
+            .build(src_ip, dst_ip, b"");
+
+        let parsed = Tcp::try_from(&tcp.serialize()[..]).unwrap();
+        assert_eq!(parsed, tcp);
+        assert!(std::collections::HashSet::from([tcp.clone()]).contains(&parsed));
+
+        // Same header, checksummed for a different destination.
+        let mut rebuilt = tcp.clone();
+        rebuilt.checksum = tcp.calculate_checksum(src_ip, Ipv4Addr::new(192, 168, 1, 3), b"");
+        assert_ne!(rebuilt, tcp);
+        assert!(rebuilt.equivalent_ignoring_checksum(&tcp));
+
+        rebuilt.seq_num += 1;
+        assert!(!rebuilt.equivalent_ignoring_checksum(&tcp));
+    }
+
+    #[test]
+    fn test_tcp_urgent_ptr_round_trip() {
+        let tcp = TcpBuilder::new()
+            .flags(TcpFlags::URG | TcpFlags::ACK)
+            .urgent_ptr(0x0102)
+            .build(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, b"urgent");
+
+        let raw_bytes = tcp.serialize();
+        assert_eq!(raw_bytes[18..20], [0x01, 0x02]);
+        assert_eq!(Tcp::try_from(&raw_bytes[..]).unwrap().urgent_ptr, 0x0102);
+        assert!(!tcp.to_string().contains("Note"));
+    }
+
+    #[test]
+    fn test_tcp_display_urg_without_pointer() {
+        let mut tcp = get_tcp();
+        tcp.flags = TcpFlags::URG;
+        assert!(tcp
+            .to_string()
+            .ends_with("Note: URG is set but the urgent pointer is 0"));
+    }
+
+    #[test]
+    fn test_tcp_ns_round_trip() {
+        let src_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let dst_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let tcp = TcpBuilder::new()
+            .flags(TcpFlags::ACK)
+            .ns(true)
+            .mss(1460)
+            .build(src_ip, dst_ip, b"");
+
+        let raw_bytes = tcp.build_packet(b"");
+        assert_eq!(raw_bytes[12], 0x61); // Data offset 6, NS.
+        assert_eq!(raw_bytes[13], TcpFlags::ACK.bits());
+
+        let parsed = Tcp::try_from(&raw_bytes[..]).unwrap();
+        assert!(parsed.ns);
+        assert_eq!(parsed.flags, TcpFlags::ACK);
+        assert!(parsed.verify_checksum(src_ip, dst_ip, b""));
+        assert!(!Tcp::try_from(&get_tcp().serialize()[..]).unwrap().ns);
+    }
+
+    #[test]
+    fn test_tcp_reserved_bits() {
+        let mut raw_bytes = get_tcp().serialize();
+        raw_bytes[12] = 0x5B; // Data offset 5, reserved 0b101, NS.
+
+        let parsed = Tcp::try_from(&raw_bytes[..]).unwrap();
+        assert_eq!(parsed.reserved, 0b101);
+        assert!(parsed.ns);
+        assert_eq!(parsed.serialize(), raw_bytes);
+        assert_eq!(get_tcp().reserved, 0);
+
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .reserved(0b010);
+        assert_eq!(builder.build(ip, ip, &[]).serialize()[12], 0x54);
+        assert_eq!(
+            builder.build_checked(ip, ip, &[]).unwrap_err(),
+            BuildError::NonZeroReserved(0b010)
+        );
+        assert!(builder.reserved(0).build_checked(ip, ip, &[]).is_ok());
+    }
+
     #[test]
     fn test_headers_build_packet_payload() {
         let payload = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
@@ -318,6 +1411,200 @@ mod tests {
         assert_eq!(packet.len(), 20 + payload.len()); // Total packet size
     }
 
+    #[test]
+    fn test_tcp_options_round_trip() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![
+            TcpOption::Mss(1460),
+            TcpOption::Nop,
+            TcpOption::WindowScale(7),
+        ];
+
+        let packet = tcp.build_packet(b"");
+        assert_eq!(packet.len(), 28);
+        assert_eq!(packet[12] >> 4, 7);
+
+        let parsed = Tcp::try_from(&packet[..]).unwrap();
+        assert_eq!(parsed.options, tcp.options);
+    }
+
+    #[test]
+    fn test_builder_mss_round_trip() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dst_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let tcp = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .mss(1460)
+            .build(src_ip, dst_ip, b"");
+
+        let packet = tcp.build_packet(b"");
+        assert_eq!(packet[12] >> 4, 6);
+        assert_eq!(&packet[20..24], [2, 4, 0x05, 0xB4]);
+
+        let mut parsed = Tcp::try_from(&packet[..]).unwrap();
+        assert_eq!(parsed.options, [TcpOption::Mss(1460)]);
+
+        // The checksum must cover the option bytes.
+        parsed.checksum = 0;
+        assert_eq!(tcp.checksum, parsed.calculate_checksum(src_ip, dst_ip, b""));
+    }
+
+    #[test]
+    fn test_tcp_data_offset_past_buffer() {
+        let mut raw_bytes = get_tcp().serialize();
+        raw_bytes[12] = 6 << 4;
+        assert_eq!(
+            Tcp::try_from(&raw_bytes[..]).unwrap_err(),
+            TcpParseError::BadDataOffset(6)
+        );
+
+        raw_bytes[12] = 4 << 4;
+        assert_eq!(
+            Tcp::try_from(&raw_bytes[..]).unwrap_err(),
+            TcpParseError::BadDataOffset(4)
+        );
+    }
+
+    #[test]
+    fn test_tcp_too_short() {
+        let raw_bytes = get_tcp().serialize();
+        assert_eq!(
+            Tcp::try_from(&raw_bytes[..19]).unwrap_err(),
+            TcpParseError::TooShort { got: 19 }
+        );
+        assert!(Tcp::parse_packet(&[]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_tcp_serde_round_trip() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Mss(1460)];
+
+        let json = serde_json::to_string(&tcp).unwrap();
+        assert!(json.contains(r#""flags":["SYN","ACK"]"#));
+
+        let parsed: Tcp = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.serialize(), tcp.serialize());
+        assert_eq!(parsed.options, tcp.options);
+    }
+
+    #[test]
+    fn test_parse_packet_raw_binary_payload() {
+        let payload = [0x00, 0xFF, 0xC3, 0x28];
+        let packet = get_tcp().build_packet(&payload);
+
+        let (tcp, raw) = Tcp::parse_packet_raw(&packet).unwrap();
+        assert_eq!(tcp.source_port, 49320);
+        assert_eq!(raw, payload);
+
+        let (_, lossy) = Tcp::parse_packet(&packet).unwrap();
+        assert_eq!(lossy.unwrap(), "\0\u{FFFD}\u{FFFD}(");
+    }
+
+    #[test]
+    fn test_parse_packet_skips_options() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Mss(1460)];
+        let packet = tcp.build_packet(b"data");
+
+        let (_, raw) = Tcp::parse_packet_raw(&packet).unwrap();
+        assert_eq!(raw, b"data");
+
+        let (_, payload) = Tcp::parse_packet(&tcp.build_packet(b"")).unwrap();
+        assert!(payload.is_none());
+    }
+
+    #[test]
+    fn test_tcp_hex_round_trip() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Mss(1460)];
+        let hex = tcp.to_hex(b"data");
+        assert!(hex.starts_with("c0a81f90"));
+
+        let (parsed, payload) = Tcp::from_hex(&hex).unwrap();
+        assert_eq!(parsed, tcp);
+        assert_eq!(payload, b"data");
+
+        assert_eq!(
+            Tcp::from_hex(&hex[1..]).unwrap_err(),
+            ParseError::OddHexLength(hex.len() - 1)
+        );
+        assert_eq!(
+            Tcp::from_hex("c0a8").unwrap_err(),
+            ParseError::Tcp(TcpParseError::TooShort { got: 2 })
+        );
+    }
+
+    #[test]
+    fn test_parse_packet_too_long() {
+        let packet = get_tcp().build_packet(&vec![0; MAX_SEGMENT_SIZE]);
+        assert_eq!(
+            Tcp::parse_packet_raw(&packet).unwrap_err(),
+            TcpParseError::TooLong {
+                got: MAX_SEGMENT_SIZE + 20,
+                max: MAX_SEGMENT_SIZE
+            }
+        );
+
+        let packet = get_tcp().build_packet(b"0123456789");
+        let mut parser = TcpParser::new();
+        assert!(parser.max_segment_size(30).parse_packet(&packet).is_ok());
+        assert_eq!(
+            parser
+                .max_segment_size(29)
+                .parse_packet(&packet)
+                .unwrap_err(),
+            TcpParseError::TooLong { got: 30, max: 29 }
+        );
+    }
+
+    #[test]
+    fn test_parse_packet_bad_data_offset() {
+        let mut packet = get_tcp().build_packet(b"data");
+
+        // Offset 4 would put the payload inside the fixed header.
+        packet[12] = 4 << 4;
+        assert_eq!(
+            Tcp::parse_packet(&packet).unwrap_err(),
+            TcpParseError::BadDataOffset(4)
+        );
+
+        // Offset 7 claims 28 bytes of header in a 24-byte segment.
+        packet[12] = 7 << 4;
+        assert_eq!(
+            Tcp::parse_packet_raw(&packet).unwrap_err(),
+            TcpParseError::BadDataOffset(7)
+        );
+
+        // Offset 6 swallows the payload as options: 4 NOPs here, while
+        // "data" doesn't hold up as options.
+        packet[12] = 6 << 4;
+        assert_eq!(
+            Tcp::parse_packet_raw(&packet).unwrap_err(),
+            TcpParseError::BadOption(b'd')
+        );
+        let mut packet = get_tcp().build_packet(&[1; 4]);
+        packet[12] = 6 << 4;
+        let (tcp, payload) = Tcp::parse_packet_raw(&packet).unwrap();
+        assert!(payload.is_empty());
+        assert_eq!(tcp.options, vec![TcpOption::Nop; 4]);
+    }
+
+    #[test]
+    fn test_tcp_every_flags_byte_parses() {
+        let mut bytes = get_tcp().serialize();
+        for raw in 0..=u8::MAX {
+            bytes[13] = raw;
+            let tcp = Tcp::try_from(&bytes[..]).unwrap();
+            assert_eq!(tcp.flags.bits(), raw);
+            assert!(!TcpFlags::has_unknown_bits(raw));
+            assert_eq!(tcp.serialize(), bytes);
+        }
+    }
+
     #[test]
     fn test_tcp_checksum_calculation() {
         let src_ip = Ipv4Addr::new(192, 168, 1, 1);
@@ -327,4 +1614,832 @@ mod tests {
         let checksum = get_tcp().calculate_checksum(src_ip, dst_ip, payload);
         assert_ne!(checksum, 0); // Ensure checksum is non-zero
     }
+
+    #[test]
+    fn test_tcp_checksum_ipv6() {
+        let ph = PseudoHeader::V6 {
+            src: "2001:db8::1".parse().unwrap(),
+            dst: "2001:db8::2".parse().unwrap(),
+        };
+        let mut tcp = get_tcp();
+        tcp.checksum = 0;
+
+        let checksum = tcp.calculate_checksum_with(ph, b"Hello, TCP!");
+        assert_eq!(checksum, 0x9745);
+
+        tcp.checksum = checksum;
+        assert!(tcp.verify_checksum_with(ph, b"Hello, TCP!"));
+    }
+
+    #[test]
+    fn test_tcp_checksum_v4_wrapper() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dst_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let ph = PseudoHeader::V4 {
+            src: src_ip,
+            dst: dst_ip,
+        };
+
+        let tcp = get_tcp();
+        assert_eq!(
+            tcp.calculate_checksum(src_ip, dst_ip, b"Hello, TCP!"),
+            tcp.calculate_checksum_with(ph, b"Hello, TCP!")
+        );
+    }
+
+    #[test]
+    fn test_tcp_incremental_port_update() {
+        let src_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let dst_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let payload = [0xAB; 1400];
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(40000)
+            .dest_port(80)
+            .seq_num(1)
+            .flags(TcpFlags::PSH_ACK);
+
+        let mut tcp = builder.build(src_ip, dst_ip, &payload);
+        tcp.set_dest_port(8080);
+        tcp.set_source_port(50000);
+
+        let recomputed = builder
+            .source_port(50000)
+            .dest_port(8080)
+            .build(src_ip, dst_ip, &payload);
+        assert_eq!(tcp.checksum, recomputed.checksum);
+        assert!(tcp.verify_checksum(src_ip, dst_ip, &payload));
+    }
+
+    #[test]
+    fn test_tcp_verify_checksum() {
+        // 192.168.0.10:51234 > 93.184.216.34:80 [PSH, ACK], odd-length payload.
+        let src_ip = Ipv4Addr::new(192, 168, 0, 10);
+        let dst_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let raw_bytes: [u8; 20] = [
+            0xC8, 0x22, 0x00, 0x50, 0x3C, 0x1A, 0x5B, 0x7E, 0x9D, 0x0E, 0x44, 0x11, 0x50, 0x18,
+            0x01, 0xF6, 0x76, 0x6B, 0x00, 0x00,
+        ];
+        let payload = b"GET / HTTP/1.0\r\n\r\n!";
+
+        let tcp = Tcp::try_from(&raw_bytes[..]).unwrap();
+        assert!(tcp.verify_checksum(src_ip, dst_ip, payload));
+        assert!(!tcp.verify_checksum(src_ip, dst_ip, b"GET / HTTP/1.0\r\n\r\n?"));
+        assert!(!tcp.verify_checksum(dst_ip, src_ip, &payload[1..]));
+    }
+
+    #[test]
+    fn test_tcp_parse_and_verify() {
+        // A SYN carrying "hello, raw TCP!", captured over loopback.
+        let mut bytes = decode_hex(
+            "d842 0000 1234 5678 8765 4321 5002 00ff 5ad0 0000 \
+             6865 6c6c 6f2c 2072 6177 2054 4350 21",
+        )
+        .unwrap();
+        let ip = Ipv4Addr::LOCALHOST;
+
+        let (tcp, payload, checksum_ok) = Tcp::parse_and_verify(&bytes, ip, ip).unwrap();
+        assert_eq!(tcp.source_port, 55362);
+        assert_eq!(tcp.flags, TcpFlags::SYN);
+        assert_eq!(payload, b"hello, raw TCP!");
+        assert!(checksum_ok);
+
+        // The checksum isn't right between other addresses.
+        let other = Ipv4Addr::new(10, 0, 0, 1);
+        assert!(!Tcp::parse_and_verify(&bytes, other, ip).unwrap().2);
+
+        // One flipped payload bit still parses, but fails the checksum.
+        bytes[25] ^= 0x01;
+        let (_, payload, checksum_ok) = Tcp::parse_and_verify(&bytes, ip, ip).unwrap();
+        assert_eq!(payload, b"hello- raw TCP!");
+        assert!(!checksum_ok);
+
+        assert!(Tcp::parse_and_verify(&bytes[..10], ip, ip).is_err());
+    }
+
+    #[test]
+    fn test_tcp_checksum_is_valid_zero() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut tcp = get_tcp();
+        tcp.urgent_ptr = 0;
+        tcp.recompute_checksum(ip, ip, b"data");
+        assert_ne!(tcp.checksum, 0);
+        assert!(!tcp.checksum_is_valid_zero(ip, ip, b"data"));
+
+        // Not computed.
+        let checksum = tcp.checksum;
+        tcp.checksum = 0;
+        assert!(!tcp.checksum_is_valid_zero(ip, ip, b"data"));
+
+        // Adding the old checksum to a zeroed word brings the sum to 0xFFFF,
+        // so the right checksum becomes 0.
+        tcp.urgent_ptr = checksum;
+        tcp.recompute_checksum(ip, ip, b"data");
+        assert_eq!(tcp.checksum, 0);
+        assert!(tcp.checksum_is_valid_zero(ip, ip, b"data"));
+        assert!(!tcp.checksum_is_valid_zero(ip, ip, b"datb"));
+    }
+
+    #[test]
+    fn test_tcp_verify_built_checksum() {
+        let src_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let dst_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let tcp = TcpBuilder::new()
+            .source_port(40000)
+            .dest_port(443)
+            .flags(TcpFlags::SYN)
+            .mss(1460)
+            .build(src_ip, dst_ip, b"odd");
+
+        assert!(tcp.verify_checksum(src_ip, dst_ip, b"odd"));
+    }
+
+    #[test]
+    fn test_tcp_build_checked_rejects_flag_combos() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let cases = [
+            (
+                TcpFlags::SYN | TcpFlags::FIN,
+                BuildError::Flags(FlagError::SynFin),
+            ),
+            (
+                TcpFlags::SYN | TcpFlags::RST,
+                BuildError::Flags(FlagError::SynRst),
+            ),
+            (
+                TcpFlags::RST | TcpFlags::FIN,
+                BuildError::Flags(FlagError::RstFin),
+            ),
+            (TcpFlags::empty(), BuildError::Flags(FlagError::Null)),
+            (TcpFlags::FIN, BuildError::Flags(FlagError::FinWithoutAck)),
+            (TcpFlags::ACK, BuildError::ZeroAckNum),
+        ];
+
+        for (flags, expected) in cases {
+            let err = TcpBuilder::new()
+                .flags(flags)
+                .build_checked(ip, ip, &[])
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+
+        let tcp = TcpBuilder::new()
+            .source_port(80)
+            .dest_port(49320)
+            .flags(TcpFlags::SYN_ACK)
+            .ack_num(1)
+            .build_checked(ip, ip, &[])
+            .unwrap();
+        assert_eq!(tcp.flags, TcpFlags::SYN_ACK);
+    }
+
+    #[test]
+    fn test_tcp_advertises_zero_window() {
+        let mut tcp = get_tcp();
+        assert!(!tcp.advertises_zero_window());
+        tcp.window_size = 0;
+        assert!(tcp.advertises_zero_window());
+        tcp.flags = TcpFlags::RST;
+        assert!(!tcp.advertises_zero_window());
+    }
+
+    #[test]
+    fn test_tcp_segment_len() {
+        let mut tcp = get_tcp();
+        tcp.flags = TcpFlags::SYN;
+        assert_eq!(tcp.segment_len(0), 1);
+
+        tcp.flags = TcpFlags::PSH_ACK;
+        assert_eq!(tcp.segment_len(100), 100);
+
+        tcp.flags = TcpFlags::FIN | TcpFlags::ACK;
+        assert_eq!(tcp.segment_len(0), 1);
+        assert_eq!(tcp.segment_len(10), 11);
+    }
+
+    #[test]
+    fn test_packet_stream_seq_wraps() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(7)
+            .flags(TcpFlags::PSH_ACK)
+            .ack_num(1);
+
+        let segments: Vec<_> = packet_stream(ip, ip, u32::MAX - 3, 3, b"ping", &builder)
+            .map(|packet| Tcp::parse_packet_raw(&packet).unwrap().0)
+            .collect();
+        let seqs: Vec<_> = segments.iter().map(|tcp| tcp.seq_num).collect();
+        assert_eq!(seqs, [u32::MAX - 3, 0, 4]);
+        for tcp in &segments {
+            assert!(tcp.verify_checksum(ip, ip, b"ping"));
+        }
+    }
+
+    #[test]
+    fn test_packet_stream_ignores_stored_payload() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(7)
+            .flags(TcpFlags::PSH_ACK)
+            .payload(b"stored");
+
+        for packet in packet_stream(ip, ip, 0, 2, b"ping", &builder) {
+            let (tcp, payload) = Tcp::parse_packet_raw(&packet).unwrap();
+            assert_eq!(payload, b"ping");
+            assert!(tcp.verify_checksum(ip, ip, b"ping"));
+        }
+    }
+
+    #[test]
+    fn test_vec_conversions_round_trip() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Mss(1460), TcpOption::SackPermitted];
+
+        let bytes = Vec::from(&tcp);
+        assert_eq!(bytes, tcp.serialize());
+        assert_eq!(Tcp::try_from(bytes).unwrap(), tcp);
+
+        let bytes: Vec<u8> = tcp.clone().into();
+        assert_eq!(
+            Tcp::try_from(bytes[..19].to_vec()),
+            Err(TcpParseError::TooShort { got: 19 })
+        );
+    }
+
+    #[test]
+    fn test_flag_predicates() {
+        let mut tcp = get_tcp();
+        tcp.flags = TcpFlags::SYN;
+        assert!(tcp.is_syn() && !tcp.is_syn_ack() && !tcp.is_ack());
+
+        // A SYN-ACK carries SYN but isn't a pure SYN.
+        tcp.flags = TcpFlags::SYN_ACK;
+        assert!(!tcp.is_syn() && tcp.is_syn_ack() && tcp.is_ack());
+
+        tcp.flags = TcpFlags::FIN | TcpFlags::PSH_ACK;
+        assert!(tcp.is_fin() && tcp.is_psh() && tcp.is_ack());
+        assert!(!tcp.is_rst() && !tcp.is_syn() && !tcp.is_syn_ack());
+
+        tcp.flags = TcpFlags::RST;
+        assert!(tcp.is_rst() && !tcp.is_ack() && !tcp.is_fin());
+    }
+
+    #[test]
+    fn test_segment_reassembles() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(80)
+            .ack_num(1)
+            .flags(TcpFlags::ACK);
+        let data: Vec<u8> = (0..=255).cycle().take(2500).collect();
+
+        let packets = segment(&data, 1000, u32::MAX - 10, &builder, ip, ip);
+        assert_eq!(packets.len(), 3);
+
+        let mut reassembled = Vec::new();
+        let mut next_seq = u32::MAX - 10;
+        for (i, packet) in packets.iter().enumerate() {
+            let (tcp, payload) = Tcp::parse_packet_raw(packet).unwrap();
+            assert!(payload.len() <= 1000);
+            assert_eq!(tcp.seq_num, next_seq);
+            assert_eq!(tcp.is_psh(), i == 2);
+            assert!(tcp.verify_checksum(ip, ip, payload));
+            next_seq = next_seq.wrapping_add(payload.len() as u32);
+            reassembled.extend_from_slice(payload);
+        }
+        assert_eq!(reassembled, data);
+        assert!(segment(b"", 1000, 0, &builder, ip, ip).is_empty());
+    }
+
+    #[test]
+    fn test_urgent_data() {
+        let mut tcp = get_tcp();
+        tcp.urgent_ptr = 3;
+        assert_eq!(tcp.urgent_data(b"abcdef"), None);
+
+        tcp.flags.insert(TcpFlags::URG);
+        // The pointer is to the byte after the urgent data, not the last one.
+        assert_eq!(tcp.urgent_data(b"abcdef"), Some(&b"abc"[..]));
+        assert_eq!(tcp.urgent_data(b"ab"), Some(&b"ab"[..]));
+
+        tcp.urgent_ptr = 0;
+        assert_eq!(tcp.urgent_data(b"abcdef"), None);
+    }
+
+    #[test]
+    fn test_flags_mut_invalidates_checksum() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut tcp = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .build(ip, ip, b"");
+        assert!(!tcp.checksum_dirty());
+
+        tcp.flags_mut().insert(TcpFlags::ACK);
+        assert!(tcp.checksum_dirty());
+        assert!(!tcp.verify_checksum(ip, ip, b""));
+
+        // The stale mark isn't part of the header.
+        let mut same = tcp.clone();
+        same.checksum_dirty = false;
+        assert_eq!(same, tcp);
+        use std::hash::{BuildHasher, RandomState};
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(&same), state.hash_one(&tcp));
+
+        tcp.recompute_checksum(ip, ip, b"");
+        assert!(!tcp.checksum_dirty());
+        assert!(tcp.verify_checksum(ip, ip, b""));
+        assert_eq!(Tcp::try_from(&tcp.serialize()[..]).unwrap(), tcp);
+    }
+
+    #[test]
+    #[should_panic(expected = "recompute_checksum")]
+    fn test_serialize_panics_on_stale_checksum() {
+        let mut tcp = get_tcp();
+        tcp.flags_mut().remove(TcpFlags::SYN);
+        tcp.build_packet(b"");
+    }
+
+    #[test]
+    fn test_builder_stored_payload() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::PSH)
+            .payload(b"Hello, TCP!");
+
+        let packet = builder.build_packet(ip, ip);
+        let (tcp, payload) = Tcp::parse_packet_raw(&packet).unwrap();
+        assert_eq!(payload, b"Hello, TCP!");
+        assert!(tcp.verify_checksum(ip, ip, payload));
+        assert_eq!(tcp, builder.build(ip, ip, b"Hello, TCP!"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "differs")]
+    fn test_builder_stored_payload_mismatch() {
+        let ip = Ipv4Addr::LOCALHOST;
+        TcpBuilder::new()
+            .payload(b"Hello, TCP!")
+            .build(ip, ip, b"hello, raw TCP!");
+    }
+
+    #[test]
+    fn test_builder_no_checksum() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let tcp = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .no_checksum()
+            .build(ip, ip, b"data");
+
+        assert_eq!(tcp.checksum, 0);
+        assert_eq!(tcp.serialize()[16..18], [0, 0]);
+        assert!(!tcp.verify_checksum(ip, ip, b"data"));
+    }
+
+    #[test]
+    fn test_builder_ecn_setup() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let syn = TcpBuilder::new()
+            .flags(TcpFlags::SYN)
+            .ecn_setup()
+            .build(ip, ip, &[]);
+        assert_eq!(syn.serialize()[13], 0b1100_0010);
+        assert!(syn.is_ecn_setup_syn());
+        assert!(!syn.is_ecn_setup_syn_ack());
+
+        // Set before the flags, and with CWR asked for by mistake.
+        let syn_ack = TcpBuilder::new()
+            .ecn_setup()
+            .flags(TcpFlags::SYN_ACK | TcpFlags::CWR)
+            .ack_num(1)
+            .build(ip, ip, &[]);
+        assert_eq!(syn_ack.serialize()[13], 0b0101_0010);
+        assert!(syn_ack.is_ecn_setup_syn_ack());
+        assert!(!syn_ack.is_ecn_setup_syn());
+
+        let ack = TcpBuilder::new()
+            .flags(TcpFlags::ACK)
+            .ecn_setup()
+            .build(ip, ip, &[]);
+        assert_eq!(ack.flags, TcpFlags::ACK);
+        assert!(!get_tcp().is_ecn_setup_syn_ack());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_builder_build_for_dest() {
+        let tcp = TcpBuilder::new()
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .build_for_dest(Ipv4Addr::LOCALHOST, b"")
+            .unwrap();
+        assert!(tcp.verify_checksum(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, b""));
+    }
+
+    #[test]
+    fn test_tcp_build_checked_zero_port() {
+        // Warned about, not rejected.
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder.flags(TcpFlags::SYN).dest_port(80);
+        assert_eq!(builder.build_checked(ip, ip, &[]).unwrap().source_port, 0);
+        assert_eq!(builder.warnings(), [BuildWarning::ZeroSourcePort]);
+
+        builder.source_port(49320).dest_port(0);
+        assert_eq!(builder.build_checked(ip, ip, &[]).unwrap().dest_port, 0);
+        assert!(builder.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_builder_ephemeral_source_port() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .ephemeral_source_only(true);
+
+        let port = builder
+            .ephemeral_source_port_seeded(7)
+            .build_checked(ip, ip, &[])
+            .unwrap()
+            .source_port;
+        assert!(port >= EPHEMERAL_PORT_START);
+        let again = builder.ephemeral_source_port_seeded(7).build(ip, ip, &[]);
+        assert_eq!(again.source_port, port);
+
+        let ports: std::collections::HashSet<u16> = (0..64)
+            .map(|seed| {
+                builder
+                    .ephemeral_source_port_seeded(seed)
+                    .build(ip, ip, &[])
+                    .source_port
+            })
+            .collect();
+        assert!(ports.iter().all(|&port| port >= EPHEMERAL_PORT_START));
+        assert!(ports.len() > 60);
+
+        #[cfg(feature = "std")]
+        {
+            let tcp = builder.ephemeral_source_port().build_checked(ip, ip, &[]);
+            assert!(tcp.unwrap().source_port >= EPHEMERAL_PORT_START);
+        }
+    }
+
+    #[test]
+    fn test_tcp_build_checked_ephemeral_source() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder.source_port(80).dest_port(8080).flags(TcpFlags::SYN);
+        assert!(builder.build_checked(ip, ip, &[]).is_ok());
+
+        builder.ephemeral_source_only(true);
+        assert_eq!(
+            builder.build_checked(ip, ip, &[]).unwrap_err(),
+            BuildError::NonEphemeralSourcePort(80)
+        );
+        assert!(builder
+            .source_port(49152)
+            .build_checked(ip, ip, &[])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_tcp_display_all_fields() {
+        let expected = "TCP Header:
+    Source Port: 49320
+    Destination Port: 8080
+    Sequence Number: 305419896
+    Acknowledge Number: 2271560481
+    Flags: SYN | ACK 18
+    Window Size: 255
+    Checksum: 0xf00d
+    Urgent Pointer: 0";
+        assert_eq!(get_tcp().to_string(), expected);
+    }
+
+    #[test]
+    fn test_tcp_display_alternate() {
+        assert_eq!(
+            format!("{:#}", get_tcp()),
+            "49320 > 8080 [SYN|ACK] seq=305419896 ack=2271560481 win=255"
+        );
+
+        let mut tcp = get_tcp();
+        tcp.flags = TcpFlags::empty();
+        assert!(format!("{:#}", tcp).contains("[none]"));
+    }
+
+    #[test]
+    fn test_tcp_window_scale() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let tcp = TcpBuilder::new()
+            .flags(TcpFlags::SYN)
+            .window_size(0xFFFF)
+            .mss(1460)
+            .window_scale(7)
+            .build(ip, ip, &[]);
+
+        let parsed = Tcp::try_from(&tcp.build_packet(&[])[..]).unwrap();
+        assert_eq!(parsed.options[1], TcpOption::WindowScale(7));
+        assert_eq!(parsed.effective_window(), 0xFFFF << 7);
+        assert_eq!(parsed.window_scale(), Some(7));
+        assert_eq!(get_tcp().effective_window(), 255);
+        assert_eq!(get_tcp().window_scale(), None);
+        assert_eq!(get_tcp().scaled_window(3), 255 << 3);
+        assert_eq!(get_tcp().scaled_window(20), 255 << 14);
+    }
+
+    #[test]
+    fn test_tcp_build_checked_window_scale_limit() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::SYN)
+            .window_scale(14);
+        assert!(builder.build_checked(ip, ip, &[]).is_ok());
+
+        let err = TcpBuilder::new()
+            .flags(TcpFlags::SYN)
+            .window_scale(15)
+            .build_checked(ip, ip, &[])
+            .unwrap_err();
+        assert_eq!(err, BuildError::WindowScaleTooLarge(15));
+    }
+
+    #[test]
+    fn test_tcp_build_checked_sack_limits() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let blocks = [(1, 2), (3, 4), (5, 6), (7, 8), (9, 10)];
+
+        let mut builder = TcpBuilder::new();
+        builder
+            .source_port(49320)
+            .dest_port(80)
+            .flags(TcpFlags::ACK)
+            .ack_num(1)
+            .sack(&blocks[..4]);
+        assert!(builder.build_checked(ip, ip, &[]).is_ok());
+
+        let err = TcpBuilder::new()
+            .flags(TcpFlags::ACK)
+            .ack_num(1)
+            .sack(&blocks)
+            .build_checked(ip, ip, &[])
+            .unwrap_err();
+        assert_eq!(err, BuildError::TooManySackBlocks(5));
+
+        builder.mss(1460).window_scale(7);
+        assert_eq!(
+            builder.build_checked(ip, ip, &[]).unwrap_err(),
+            BuildError::OptionsTooLong(44)
+        );
+    }
+
+    #[test]
+    fn test_tcp_try_serialize_limits() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Sack(vec![(1, 2); 4])];
+        assert_eq!(tcp.try_serialize().unwrap().len(), 20 + 36);
+
+        tcp.options = vec![TcpOption::Sack(vec![(1, 2); 5])];
+        assert_eq!(tcp.try_serialize(), Err(BuildError::TooManySackBlocks(5)));
+
+        tcp.options = vec![
+            TcpOption::Mss(1460),
+            TcpOption::WindowScale(7),
+            TcpOption::Timestamp { tsval: 1, tsecr: 2 },
+            TcpOption::Sack(vec![(1, 2); 4]),
+        ];
+        assert_eq!(tcp.try_serialize(), Err(BuildError::OptionsTooLong(52)));
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 40 fit")]
+    fn test_tcp_data_offset_rejects_long_options() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Unknown {
+            kind: 99,
+            data: vec![0; 40],
+        }];
+        tcp.data_offset();
+    }
+
+    #[test]
+    fn test_tcp_timestamp_data_offset() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let tcp = TcpBuilder::new()
+            .flags(TcpFlags::ACK)
+            .ack_num(1)
+            .timestamp(1000, 500)
+            .build(ip, ip, &[]);
+        assert_eq!(tcp.data_offset(), 8);
+
+        let packet = tcp.build_packet(b"data");
+        assert_eq!(packet.len(), 32 + 4);
+        assert_eq!(packet[12] >> 4, 8);
+
+        let (parsed, payload) = Tcp::parse_packet_raw(&packet).unwrap();
+        assert_eq!(payload, b"data");
+        assert_eq!(
+            parsed.options[2],
+            TcpOption::Timestamp {
+                tsval: 1000,
+                tsecr: 500
+            }
+        );
+    }
+
+    #[test]
+    fn test_tcp_rst_for_segment_with_ack() {
+        let src_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let dst_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let incoming = TcpBuilder::new()
+            .source_port(40000)
+            .dest_port(80)
+            .seq_num(1000)
+            .ack_num(5000)
+            .flags(TcpFlags::PSH_ACK)
+            .build(src_ip, dst_ip, b"data");
+
+        let rst = incoming.rst_for(src_ip, dst_ip, 4).unwrap();
+        assert_eq!((rst.source_port, rst.dest_port), (80, 40000));
+        assert_eq!(rst.flags, TcpFlags::RST);
+        assert_eq!(rst.seq_num, 5000);
+        assert!(rst.verify_checksum(dst_ip, src_ip, b""));
+    }
+
+    #[test]
+    fn test_tcp_rst_for_segment_without_ack() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut incoming = get_tcp();
+        incoming.flags = TcpFlags::SYN;
+        incoming.seq_num = u32::MAX;
+
+        // The SYN occupies one sequence number, and the ack wraps.
+        let rst = incoming.rst_for(ip, ip, 0).unwrap();
+        assert_eq!(rst.flags, TcpFlags::RST_ACK);
+        assert_eq!(rst.seq_num, 0);
+        assert_eq!(rst.ack_num, 0);
+
+        incoming.flags = TcpFlags::FIN | TcpFlags::PSH;
+        incoming.seq_num = 100;
+        let rst = incoming.rst_for(ip, ip, 10).unwrap();
+        assert_eq!(rst.ack_num, 111);
+    }
+
+    #[test]
+    fn test_tcp_rst_for_rst_is_none() {
+        let ip = Ipv4Addr::LOCALHOST;
+        let mut incoming = get_tcp();
+        incoming.flags = TcpFlags::RST_ACK;
+        assert!(incoming.rst_for(ip, ip, 0).is_none());
+    }
+
+    #[test]
+    fn test_tcp_is_keepalive() {
+        let rcv_nxt = 1001;
+        let mut probe = get_tcp();
+        probe.flags = TcpFlags::ACK;
+        probe.seq_num = 1000;
+
+        // At `rcv_nxt - 1`. Linux: no payload. Windows: one garbage byte.
+        assert!(probe.is_keepalive(rcv_nxt, 0));
+        assert!(probe.is_keepalive(rcv_nxt, 1));
+        assert!(!probe.is_keepalive(rcv_nxt, 2));
+
+        // At `rcv_nxt`: the probe is the same segment as a duplicate ACK, so
+        // it can't be detected. With a byte, it's data.
+        let mut at_rcv_nxt = probe.clone();
+        at_rcv_nxt.seq_num = rcv_nxt;
+        let mut dup_ack = get_tcp();
+        dup_ack.flags = TcpFlags::ACK;
+        dup_ack.seq_num = rcv_nxt;
+        assert_eq!(at_rcv_nxt, dup_ack);
+        assert!(!at_rcv_nxt.is_keepalive(rcv_nxt, 0));
+        assert!(!at_rcv_nxt.is_keepalive(rcv_nxt, 1));
+
+        // Sequence numbers wrap.
+        probe.seq_num = u32::MAX;
+        assert!(probe.is_keepalive(0, 0));
+
+        probe.seq_num = 1000;
+        probe.flags = TcpFlags::FIN_ACK;
+        assert!(!probe.is_keepalive(rcv_nxt, 0));
+        probe.flags = TcpFlags::SYN_ACK;
+        assert!(!probe.is_keepalive(rcv_nxt, 0));
+    }
+
+    #[test]
+    fn test_tcp_header_len_with_options() {
+        let mut tcp = get_tcp();
+        assert_eq!((tcp.data_offset(), tcp.header_len()), (5, 20));
+        assert_eq!(tcp.serialize(), tcp.fixed_header());
+
+        tcp.options = vec![TcpOption::Mss(1460), TcpOption::WindowScale(7)];
+        assert_eq!((tcp.data_offset(), tcp.header_len()), (7, 28));
+
+        let bytes = tcp.serialize();
+        assert_eq!(bytes.len(), tcp.header_len());
+        assert_eq!(bytes[12] >> 4, 7);
+        assert_eq!(bytes[20..], [2, 4, 0x05, 0xB4, 3, 3, 7, 0]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    #[should_panic(expected = "can't hold TCP options")]
+    fn test_tcp_to_bytes_rejects_options() {
+        let mut tcp = get_tcp();
+        tcp.options = vec![TcpOption::Mss(1460)];
+        tcp.to_bytes();
+    }
+
+    fn any_option() -> impl Strategy<Value = TcpOption> {
+        prop_oneof![
+            Just(TcpOption::Nop),
+            any::<u16>().prop_map(TcpOption::Mss),
+            any::<u8>().prop_map(TcpOption::WindowScale),
+            Just(TcpOption::SackPermitted),
+            prop::collection::vec(any::<(u32, u32)>(), 1..=MAX_SACK_BLOCKS)
+                .prop_map(TcpOption::Sack),
+            any::<(u32, u32)>().prop_map(|(tsval, tsecr)| TcpOption::Timestamp { tsval, tsecr }),
+            // Kinds past 8 so they can't reparse as a known option.
+            (9..=u8::MAX, prop::collection::vec(any::<u8>(), 0..8))
+                .prop_map(|(kind, data)| TcpOption::Unknown { kind, data }),
+        ]
+    }
+
+    prop_compose! {
+        fn any_tcp()(
+            (source_port, dest_port, seq_num, ack_num) in any::<(u16, u16, u32, u32)>(),
+            flags in any::<u8>(),
+            ns in any::<bool>(),
+            reserved in 0..8u8,
+            (window_size, checksum, urgent_ptr) in any::<(u16, u16, u16)>(),
+            options in prop::collection::vec(any_option(), 0..4)
+                .prop_filter("options must fit in 40 bytes", |options| {
+                    options::to_bytes(options).len() <= MAX_OPTIONS_LEN
+                }),
+        ) -> Tcp {
+            Tcp {
+                source_port,
+                dest_port,
+                seq_num,
+                ack_num,
+                flags: TcpFlags::from_bits_retain(flags),
+                ns,
+                reserved,
+                window_size,
+                checksum,
+                urgent_ptr,
+                options,
+                checksum_dirty: false,
+            }
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_tcp_round_trips() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        for seed in 0..256u64 {
+            let bytes: Vec<u8> = (0..256)
+                .flat_map(|i| splitmix64(seed << 8 | i).to_le_bytes())
+                .collect();
+            let tcp = Tcp::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            assert!(tcp.options_bytes().len() <= MAX_OPTIONS_LEN);
+            assert_eq!(Tcp::try_from(&tcp.serialize()[..]).unwrap(), tcp);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_tcp_round_trip_any(tcp in any_tcp()) {
+            prop_assert_eq!(Tcp::try_from(&tcp.serialize()[..]).unwrap(), tcp);
+        }
+
+        #[test]
+        fn test_tcp_parse_any_bytes_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..128)) {
+            let _ = Tcp::try_from(&bytes[..]);
+            let _ = Tcp::parse_packet(&bytes);
+        }
+    }
 }
@@ -0,0 +1,186 @@
+use crate::checksum::{self, PseudoHeader, PROTOCOL_UDP};
+use crate::error::UdpParseError;
+use alloc::vec::Vec;
+use core::{fmt, net::Ipv4Addr};
+
+#[derive(Debug)]
+pub struct Udp {
+    pub source_port: u16,
+    pub dest_port: u16,
+    /// Length of the header plus payload in bytes.
+    pub length: u16,
+    pub checksum: u16,
+}
+
+impl fmt::Display for Udp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"UDP Header:
+    Source Port: {}
+    Destination Port: {}
+    Length: {}"#,
+            self.source_port, self.dest_port, self.length
+        )
+    }
+}
+
+impl Udp {
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[0..2].copy_from_slice(&self.source_port.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.dest_port.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.length.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes
+    }
+
+    /// Returns: 16-bit ones' complement of the ones' complement sum of the
+    /// IPv4 pseudo-header (protocol 17), the UDP header and the payload,
+    /// the same way as TCP.
+    pub fn calculate_checksum(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> u16 {
+        let ph = PseudoHeader::V4 {
+            src: src_ip,
+            dst: dst_ip,
+        };
+        let udp_length = self.to_bytes().len() + payload.len();
+
+        let mut sum = ph.sum(PROTOCOL_UDP, udp_length as u32);
+        sum += checksum::ones_complement_sum(&self.to_bytes()) as u32;
+        sum += checksum::ones_complement_sum(payload) as u32;
+
+        !checksum::fold(sum)
+    }
+
+    pub fn build_packet(&self, payload: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&self.to_bytes());
+        packet.extend_from_slice(payload);
+
+        packet
+    }
+}
+
+/// Converts a slice of bytes into a `Udp` instance.
+///
+/// # Errors
+/// - `TooShort` if the provided `bytes` slice is less than 8 bytes long.
+/// - `BadLength` if the length field is below 8 or past the end of `bytes`.
+impl TryFrom<&[u8]> for Udp {
+    type Error = UdpParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 8 {
+            return Err(UdpParseError::TooShort { got: bytes.len() });
+        }
+
+        let length = u16::from_be_bytes(bytes[4..6].try_into().unwrap());
+        if length < 8 || length as usize > bytes.len() {
+            return Err(UdpParseError::BadLength(length));
+        }
+
+        Ok(Self {
+            source_port: u16::from_be_bytes(bytes[0..2].try_into().unwrap()),
+            dest_port: u16::from_be_bytes(bytes[2..4].try_into().unwrap()),
+            length,
+            checksum: u16::from_be_bytes(bytes[6..8].try_into().unwrap()),
+        })
+    }
+}
+
+pub struct UdpBuilder {
+    source_port: u16,
+    dest_port: u16,
+}
+
+impl Default for UdpBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UdpBuilder {
+    pub fn new() -> Self {
+        Self {
+            source_port: 0,
+            dest_port: 0,
+        }
+    }
+
+    pub fn source_port(&mut self, port: u16) -> &mut Self {
+        self.source_port = port;
+        self
+    }
+
+    pub fn dest_port(&mut self, port: u16) -> &mut Self {
+        self.dest_port = port;
+        self
+    }
+
+    pub fn build(&self, src_ip: Ipv4Addr, dst_ip: Ipv4Addr, payload: &[u8]) -> Udp {
+        let mut udp = Udp {
+            source_port: self.source_port,
+            dest_port: self.dest_port,
+            length: (8 + payload.len()) as u16,
+            checksum: 0,
+        };
+
+        // A zero checksum means "no checksum" in UDP, so a computed zero is
+        // sent as its ones' complement equivalent, all ones.
+        let checksum = udp.calculate_checksum(src_ip, dst_ip, payload);
+        udp.checksum = if checksum == 0 { 0xFFFF } else { checksum };
+
+        udp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_udp_round_trip() {
+        let udp = Udp {
+            source_port: 5353,
+            dest_port: 53,
+            length: 19,
+            checksum: 0x2D04,
+        };
+        let packet = udp.build_packet(b"Hello, UDP!");
+        assert_eq!(
+            &packet[..8],
+            [0x14, 0xE9, 0x00, 0x35, 0x00, 0x13, 0x2D, 0x04]
+        );
+
+        let parsed = Udp::try_from(&packet[..]).unwrap();
+        assert_eq!(parsed.source_port, 5353);
+        assert_eq!(parsed.dest_port, 53);
+        assert_eq!(parsed.length, 19);
+        assert_eq!(parsed.checksum, 0x2D04);
+    }
+
+    #[test]
+    fn test_udp_builder_checksum() {
+        let udp = UdpBuilder::new().source_port(5353).dest_port(53).build(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            b"Hello, UDP!",
+        );
+
+        assert_eq!(udp.length, 19);
+        assert_eq!(udp.checksum, 0x2D04);
+    }
+
+    #[test]
+    fn test_udp_invalid() {
+        assert_eq!(
+            Udp::try_from(&[0u8; 7][..]).unwrap_err(),
+            UdpParseError::TooShort { got: 7 }
+        );
+        assert_eq!(
+            Udp::try_from(&[0, 1, 0, 2, 0, 9, 0, 0][..]).unwrap_err(),
+            UdpParseError::BadLength(9)
+        );
+    }
+}